@@ -1,39 +1,528 @@
 use crate::day_eight::Op::{Acc, Jump, Nop};
-use crate::utils::split_once_at;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 enum Op {
     Jump(isize),
     Nop(isize),
     Acc(isize),
 }
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Jump(value) => write!(f, "jmp {:+}", value),
+            Nop(value) => write!(f, "nop {:+}", value),
+            Acc(value) => write!(f, "acc {:+}", value),
+        }
+    }
+}
+
+const REGISTERS: usize = 4;
+
+/// Values waiting on an input port. Reading moves a cursor rather than removing anything, so a
+/// run can be rewound just by moving the cursor back.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct InputQueue {
+    values: Vec<isize>,
+    next: usize,
+}
+
+impl InputQueue {
+    fn remaining(&self) -> usize {
+        self.values.len() - self.next
+    }
+}
+
+/// Numbered input and output ports, each one its own queue
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Ports {
+    input: BTreeMap<usize, InputQueue>,
+    output: BTreeMap<usize, Vec<isize>>,
+}
+
+// how far along every port is, enough to put the ports back where they were
+#[derive(Debug, Clone, PartialEq)]
+struct PortPositions {
+    input: Vec<(usize, usize)>,
+    output: Vec<(usize, usize)>,
+}
+
+impl Ports {
+    fn feed(&mut self, port: usize, values: impl IntoIterator<Item = isize>) {
+        self.input.entry(port).or_default().values.extend(values)
+    }
+
+    fn read(&mut self, port: usize) -> Result<isize, Box<dyn Error>> {
+        let queue = self
+            .input
+            .get_mut(&port)
+            .filter(|queue| queue.remaining() > 0)
+            .ok_or_else(|| format!("Port {} has no input", port))?;
+        queue.next += 1;
+        Ok(queue.values[queue.next - 1])
+    }
+
+    fn positions(&self) -> PortPositions {
+        PortPositions {
+            input: self
+                .input
+                .iter()
+                .map(|(&port, queue)| (port, queue.next))
+                .collect(),
+            output: self
+                .output
+                .iter()
+                .map(|(&port, values)| (port, values.len()))
+                .collect(),
+        }
+    }
+
+    // ports missing from the positions hadn't been touched yet. Values fed in since stay fed.
+    fn rewind(&mut self, positions: &PortPositions) {
+        let position = |positions: &[(usize, usize)], port| {
+            positions
+                .iter()
+                .find(|&&(known, _)| known == port)
+                .map_or(0, |&(_, position)| position)
+        };
+        for (port, queue) in self.input.iter_mut() {
+            queue.next = position(&positions.input, *port);
+        }
+        for (port, values) in self.output.iter_mut() {
+            values.truncate(position(&positions.output, *port));
+        }
+    }
+
+    fn write(&mut self, port: usize, value: isize) {
+        self.output.entry(port).or_default().push(value)
+    }
+
+    fn output(&self, port: usize) -> &[isize] {
+        self.output
+            .get(&port)
+            .map_or(&[], |values| values.as_slice())
+    }
+}
+
+/// The parts of the machine an operation may change while it runs
+struct Cpu<'a> {
+    acc: &'a mut isize,
+    registers: &'a mut [isize; REGISTERS],
+    ports: &'a mut Ports,
+}
+
+/// An instruction set the VM can run. Implementing this is how the handheld learns new opcodes.
+trait Operation: Copy + PartialEq + fmt::Debug + fmt::Display {
+    // when control flow can't depend on the machine's state, running an instruction a second time
+    // means the program will never stop. Otherwise a loop is only certain once the whole machine
+    // state comes around again, which costs a record of every state seen.
+    const REPEAT_MEANS_LOOP: bool;
+
+    fn parse(source: &str) -> Result<Self, Box<dyn Error>>;
+
+    // runs the operation and returns how far to move the program counter
+    fn execute(&self, cpu: &mut Cpu<'_>) -> Result<isize, Box<dyn Error>>;
+}
+
+fn parse_operand<T: std::str::FromStr>(operand: &str) -> Result<T, Box<dyn Error>> {
+    operand
+        .parse()
+        .map_err(|_| format!("Invalid operand {:?}", operand).into())
+}
+
+impl Operation for Op {
+    const REPEAT_MEANS_LOOP: bool = true;
+
+    fn parse(source: &str) -> Result<Self, Box<dyn Error>> {
+        let (operation, parameters) = source
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| format!("Missing operand in {:?}", source))?;
+        match operation {
+            "jmp" => Ok(Jump(parse_operand(parameters)?)),
+            "acc" => Ok(Acc(parse_operand(parameters)?)),
+            "nop" => Ok(Nop(parse_operand(parameters)?)),
+            _ => Err(format!("Unknown instruction {:?}", operation).into()),
+        }
+    }
+
+    fn execute(&self, cpu: &mut Cpu<'_>) -> Result<isize, Box<dyn Error>> {
+        match *self {
+            Jump(offset) => Ok(offset),
+            Nop(_) => Ok(1),
+            Acc(amount) => {
+                *cpu.acc = cpu
+                    .acc
+                    .checked_add(amount)
+                    .ok_or("The accumulator overflowed")?;
+                Ok(1)
+            }
+        }
+    }
+}
+
+/// The handheld's instructions plus arithmetic, conditional jumps, registers r0 to r3 and ports
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ExtendedOp {
+    Base(Op),
+    // mul +3, acc = acc * 3
+    Multiply(isize),
+    // jz +2, jump when acc is 0
+    JumpIfZero(isize),
+    // jnz -4, jump when acc is not 0
+    JumpIfNotZero(isize),
+    // lda r1, acc = r1
+    Load(usize),
+    // sta r1, r1 = acc
+    Store(usize),
+    // add r1, acc = acc + r1
+    AddRegister(usize),
+    // in 0, acc = the next value waiting on port 0
+    Input(usize),
+    // out 0, send acc to port 0
+    Output(usize),
+}
+
+fn parse_register(operand: &str) -> Result<usize, Box<dyn Error>> {
+    match operand.strip_prefix('r').map(str::parse::<usize>) {
+        Some(Ok(register)) if register < REGISTERS => Ok(register),
+        _ => Err(format!("Invalid register {:?}", operand).into()),
+    }
+}
+
+impl Operation for ExtendedOp {
+    // conditional jumps mean a repeated instruction can still go somewhere new
+    const REPEAT_MEANS_LOOP: bool = false;
+
+    fn parse(source: &str) -> Result<Self, Box<dyn Error>> {
+        let (operation, operand) = source
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| format!("Missing operand in {:?}", source))?;
+        match operation {
+            "mul" => Ok(ExtendedOp::Multiply(parse_operand(operand)?)),
+            "jz" => Ok(ExtendedOp::JumpIfZero(parse_operand(operand)?)),
+            "jnz" => Ok(ExtendedOp::JumpIfNotZero(parse_operand(operand)?)),
+            "lda" => Ok(ExtendedOp::Load(parse_register(operand)?)),
+            "sta" => Ok(ExtendedOp::Store(parse_register(operand)?)),
+            "add" => Ok(ExtendedOp::AddRegister(parse_register(operand)?)),
+            "in" => Ok(ExtendedOp::Input(parse_operand(operand)?)),
+            "out" => Ok(ExtendedOp::Output(parse_operand(operand)?)),
+            _ => Ok(ExtendedOp::Base(Op::parse(source)?)),
+        }
+    }
+
+    fn execute(&self, cpu: &mut Cpu<'_>) -> Result<isize, Box<dyn Error>> {
+        match *self {
+            ExtendedOp::Base(operation) => return operation.execute(cpu),
+            ExtendedOp::Multiply(factor) => {
+                *cpu.acc = cpu
+                    .acc
+                    .checked_mul(factor)
+                    .ok_or("Multiplication overflowed the accumulator")?
+            }
+            ExtendedOp::JumpIfZero(offset) if *cpu.acc == 0 => return Ok(offset),
+            ExtendedOp::JumpIfNotZero(offset) if *cpu.acc != 0 => return Ok(offset),
+            ExtendedOp::JumpIfZero(_) | ExtendedOp::JumpIfNotZero(_) => {}
+            ExtendedOp::Load(register) => *cpu.acc = cpu.registers[register],
+            ExtendedOp::Store(register) => cpu.registers[register] = *cpu.acc,
+            ExtendedOp::AddRegister(register) => {
+                *cpu.acc = cpu
+                    .acc
+                    .checked_add(cpu.registers[register])
+                    .ok_or("The accumulator overflowed")?
+            }
+            ExtendedOp::Input(port) => *cpu.acc = cpu.ports.read(port)?,
+            ExtendedOp::Output(port) => cpu.ports.write(port, *cpu.acc),
+        }
+
+        Ok(1)
+    }
+}
+
+impl fmt::Display for ExtendedOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtendedOp::Base(operation) => write!(f, "{}", operation),
+            ExtendedOp::Multiply(factor) => write!(f, "mul {:+}", factor),
+            ExtendedOp::JumpIfZero(offset) => write!(f, "jz {:+}", offset),
+            ExtendedOp::JumpIfNotZero(offset) => write!(f, "jnz {:+}", offset),
+            ExtendedOp::Load(register) => write!(f, "lda r{}", register),
+            ExtendedOp::Store(register) => write!(f, "sta r{}", register),
+            ExtendedOp::AddRegister(register) => write!(f, "add r{}", register),
+            ExtendedOp::Input(port) => write!(f, "in {}", port),
+            ExtendedOp::Output(port) => write!(f, "out {}", port),
+        }
+    }
+}
+
+// program counters are zero based indexes into the instructions, only the text shown to people
+// talks in one based line numbers
 #[derive(Debug)]
-struct Program {
+struct Program<O = Op> {
     trace: Vec<isize>,
     acc: isize,
+    registers: [isize; REGISTERS],
+    ports: Ports,
+    program_counter: isize,
+    instructions: Vec<Instruction<O>>,
+    // only recorded once tracing is switched on
+    events: Option<Vec<TraceEvent<O>>>,
+    // every state run from and the step it was at, only kept when REPEAT_MEANS_LOOP is false
+    visited: HashMap<MachineState, usize>,
+    // the step each instruction first ran at, in order, so restoring can clear executed flags
+    first_runs: Vec<(usize, usize)>,
+}
+
+// everything that decides what happens next. Output can't change that so it is left out, and
+// input only ever shrinks so how much is left on each port is enough.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct MachineState {
+    program_counter: isize,
+    acc: isize,
+    registers: [isize; REGISTERS],
+    input_remaining: Vec<(usize, usize)>,
+}
+
+/// The machine state at one point in a run, a handful of numbers however long the program is.
+/// Restoring only rewinds: everything done after the snapshot is undone from the program's own
+/// logs, so it can't jump forward again.
+#[derive(Debug, Clone, PartialEq)]
+struct Snapshot {
     program_counter: isize,
-    instructions: Vec<Instruction>,
+    acc: isize,
+    registers: [isize; REGISTERS],
+    ports: PortPositions,
+    steps: usize,
+}
+
+/// The most recent snapshots, the oldest are dropped once there are more than capacity
+#[derive(Debug)]
+struct History {
+    snapshots: VecDeque<Snapshot>,
+    capacity: usize,
+}
+
+impl History {
+    fn new(capacity: usize) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, snapshot: Snapshot) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    fn pop(&mut self) -> Option<Snapshot> {
+        self.snapshots.pop_back()
+    }
+
+    fn clear(&mut self) {
+        self.snapshots.clear()
+    }
+}
+
+/// One step of a traced run
+#[derive(Debug, Clone, PartialEq)]
+struct TraceEvent<O = Op> {
+    step: usize,
+    pc: isize,
+    operation: O,
+    acc_before: isize,
+    acc_after: isize,
+}
+
+impl<O: Operation> TraceEvent<O> {
+    // operands that are numbers stay numbers, register names and the like become strings
+    fn to_json(&self) -> String {
+        let text = self.operation.to_string();
+        let (opcode, operand) = text.split_once(' ').unwrap_or((&text, ""));
+        let operand = match operand.parse::<isize>() {
+            Ok(value) => value.to_string(),
+            Err(_) => format!(
+                r#""{}""#,
+                operand.replace('\\', r"\\").replace('"', r#"\""#)
+            ),
+        };
+
+        format!(
+            r#"{{"step":{},"pc":{},"opcode":"{}","operand":{},"acc_before":{},"acc_after":{}}}"#,
+            self.step, self.pc, opcode, operand, self.acc_before, self.acc_after
+        )
+    }
+}
+
+/// A backward jump that was taken, the body of the loop runs from start to end
+#[derive(Debug, PartialEq)]
+struct HotLoop {
+    start: isize,
+    end: isize,
+    iterations: usize,
+}
+
+/// How often each instruction ran and which loops the run spent its time in
+#[derive(Debug, PartialEq)]
+struct Profile {
+    counts: Vec<usize>,
+    hot_loops: Vec<HotLoop>,
+}
+
+impl Profile {
+    // the last pc in a trace is where the program stopped so it never ran
+    fn from_trace(trace: &[isize], length: usize) -> Self {
+        let mut counts = vec![0; length];
+        let mut loops: HashMap<(isize, isize), usize> = HashMap::new();
+        for pair in trace.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            counts[from as usize] += 1;
+            if to <= from {
+                *loops.entry((to, from)).or_default() += 1;
+            }
+        }
+
+        let mut hot_loops: Vec<HotLoop> = loops
+            .into_iter()
+            .map(|((start, end), iterations)| HotLoop {
+                start,
+                end,
+                iterations,
+            })
+            .collect();
+        hot_loops.sort_by_key(|hot_loop| (std::cmp::Reverse(hot_loop.iterations), hot_loop.start));
+
+        Self { counts, hot_loops }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "line  runs")?;
+        for (index, count) in self.counts.iter().enumerate() {
+            writeln!(f, "{:>4}  {:>4}", index + 1, count)?;
+        }
+        writeln!(f, "hot loops:")?;
+        for hot_loop in self.hot_loops.iter() {
+            writeln!(
+                f,
+                "  lines {}-{} jumped back {} times",
+                hot_loop.start + 1,
+                hot_loop.end + 1,
+                hot_loop.iterations
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Why the program can't take another step. Checked before every step, so unlike
+/// ExecutionOutcome it carries nothing that has to be copied.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Halt {
+    Terminated,
+    InfiniteLoop,
+    JumpOutOfBounds,
+}
+
+/// How a run of the program came to an end. Every outcome carries the accumulator and the trace
+/// of program counters at the point it stopped.
+#[derive(Debug, PartialEq)]
+enum ExecutionOutcome {
+    // the program counter landed exactly one past the last instruction
+    Terminated {
+        acc: isize,
+        trace: Vec<isize>,
+    },
+    // the instruction at pc was about to run a second time
+    InfiniteLoop {
+        pc: isize,
+        acc: isize,
+        trace: Vec<isize>,
+    },
+    // a jump left pc before the first instruction or past the end
+    JumpOutOfBounds {
+        pc: isize,
+        acc: isize,
+        trace: Vec<isize>,
+    },
+    StepLimitExceeded {
+        steps: usize,
+        acc: isize,
+        trace: Vec<isize>,
+    },
+    // the instruction at pc couldn't run, eg: reading from an empty port
+    Fault {
+        pc: isize,
+        message: String,
+        acc: isize,
+        trace: Vec<isize>,
+    },
+}
+
+impl ExecutionOutcome {
+    fn acc(&self) -> isize {
+        match self {
+            ExecutionOutcome::Terminated { acc, .. }
+            | ExecutionOutcome::InfiniteLoop { acc, .. }
+            | ExecutionOutcome::JumpOutOfBounds { acc, .. }
+            | ExecutionOutcome::StepLimitExceeded { acc, .. }
+            | ExecutionOutcome::Fault { acc, .. } => *acc,
+        }
+    }
+
+    fn trace(&self) -> &[isize] {
+        match self {
+            ExecutionOutcome::Terminated { trace, .. }
+            | ExecutionOutcome::InfiniteLoop { trace, .. }
+            | ExecutionOutcome::JumpOutOfBounds { trace, .. }
+            | ExecutionOutcome::StepLimitExceeded { trace, .. }
+            | ExecutionOutcome::Fault { trace, .. } => trace,
+        }
+    }
+}
+
+impl fmt::Display for ExecutionOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionOutcome::Terminated { acc, .. } => {
+                write!(f, "The program terminated with acc {}", acc)
+            }
+            ExecutionOutcome::InfiniteLoop { pc, .. } => {
+                write!(f, "Line {} has already run, the program is looping", pc + 1)
+            }
+            ExecutionOutcome::JumpOutOfBounds { pc, .. } => {
+                // widened so a jump to the very edge of isize can still be reported
+                write!(f, "Jumped out of the program to line {}", *pc as i128 + 1)
+            }
+            ExecutionOutcome::StepLimitExceeded { steps, .. } => {
+                write!(f, "Gave up after {} steps", steps)
+            }
+            ExecutionOutcome::Fault { pc, message, .. } => {
+                write!(f, "Line {} failed: {}", pc + 1, message)
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
-struct Instruction {
-    operation: Op,
+struct Instruction<O = Op> {
+    operation: O,
     executed: bool,
 }
 
 impl Instruction {
     fn parse_from_text(source: &'static str) -> Self {
-        let (operation, parameters) = split_once_at(source, " ");
-        let operation = match operation {
-            "jmp" => Jump(parameters.parse().unwrap()),
-            "acc" => Acc(parameters.parse().unwrap()),
-            "nop" => Nop(parameters.parse().unwrap()),
-            _ => panic!("Unable to parse instruction"),
-        };
-
         Self {
-            operation,
+            operation: Op::parse(source).expect("Unable to parse instruction"),
             executed: false,
         }
     }
@@ -44,139 +533,2303 @@ impl Program {
         let instructions = source.lines().map(Instruction::parse_from_text).collect();
         Self {
             acc: 0,
-            program_counter: 1,
-            instructions: instructions,
-            trace: vec![1],
+            registers: [0; REGISTERS],
+            ports: Ports::default(),
+            program_counter: 0,
+            instructions,
+            trace: vec![0],
+            events: None,
+            visited: HashMap::new(),
+            first_runs: vec![],
         }
     }
 
-    fn reset_program(&mut self) {
-        self.trace.clear();
-        self.program_counter = 1;
-        self.acc = 0;
-        for instruction in self.instructions.iter_mut() {
-            instruction.executed = false;
-        }
+    // the line a jmp or nop on `line` points at
+    fn jump_target(line: usize, offset: isize) -> isize {
+        (line as isize).saturating_add(offset)
     }
 
-    fn program_step(&mut self) -> Result<(), Box<dyn Error>> {
-        let instruction = &mut self.instructions[self.program_counter as usize - 1]; // expect this to panic with index out of bounds access
-
-        if instruction.executed {
-            return Err("Previously Executed Instruction".into());
-        }
-
-        match instruction.operation {
-            Jump(pos) => self.program_counter += pos,
-            Nop(_) => self.program_counter += 1,
-            Acc(amt) => {
-                self.acc += amt;
-                self.program_counter += 1
-            }
+    /// A listing with line numbers where every jmp and nop shows the line it points at
+    fn disassemble(&self) -> String {
+        let mut output = String::new();
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            output += &Self::listing(index + 1, instruction.operation);
+            output.push('\n');
         }
 
-        instruction.executed = true;
-        self.trace.push(self.program_counter);
+        output
+    }
 
-        if self.program_counter < 0 {
-            panic!("Program counter has gone to negatives, the world is a lie")
+    // a single disassembled line, annotated with where jmp and nop point
+    fn listing(line: usize, operation: Op) -> String {
+        let listing = format!("{:>4}  {}", line, operation);
+        match operation {
+            Jump(offset) => format!("{:<16}; -> {}", listing, Self::jump_target(line, offset)),
+            Nop(offset) => format!(
+                "{:<16}; would jump -> {}",
+                listing,
+                Self::jump_target(line, offset)
+            ),
+            Acc(_) => listing,
         }
-
-        Ok(())
     }
 
-    fn execute(&mut self) -> Result<(), Box<dyn Error>> {
-        loop {
-            self.program_step()?;
-            if self.program_counter as usize >= self.instructions.len() {
-                // program has come to a halt
-                return Ok(());
+    /// Assembler source for the program with a label on every line that is jumped to
+    fn to_assembly(&self) -> String {
+        let length = self.instructions.len() as isize;
+        let mut targets = self
+            .instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, instruction)| match instruction.operation {
+                Jump(offset) | Nop(offset) => Some(Self::jump_target(index + 1, offset)),
+                Acc(_) => None,
+            })
+            .filter(|&target| target >= 1 && target <= length + 1)
+            .collect::<Vec<isize>>();
+        targets.sort_unstable();
+        targets.dedup();
+
+        let label = |line: isize| format!("l{}", line);
+        let mut output = String::new();
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            let line = index as isize + 1;
+            if targets.binary_search(&line).is_ok() {
+                output += &format!("{}:\n", label(line));
             }
+            output += &match instruction.operation {
+                Jump(offset) | Nop(offset)
+                    if targets
+                        .binary_search(&Self::jump_target(index + 1, offset))
+                        .is_ok() =>
+                {
+                    let mnemonic = if let Jump(_) = instruction.operation {
+                        "jmp"
+                    } else {
+                        "nop"
+                    };
+                    format!(
+                        "    {} {}\n",
+                        mnemonic,
+                        label(Self::jump_target(index + 1, offset))
+                    )
+                }
+                operation => format!("    {}\n", operation),
+            };
+        }
+        if targets.binary_search(&(length + 1)).is_ok() {
+            output += &format!("{}:\n", label(length + 1));
         }
+
+        output
     }
 
     // will attempt to backtrace itself after an error and attempt to correct a flipped instruction
     // consumes self and returns a new uninited program
     fn self_debug(mut self) -> Self {
-        // walk over all of the instructions going backwards and attempt to rerun the program
-        let mut trace = self.trace.clone();
+        // nothing runs twice before the loop is found, so a flip can't change the path leading up
+        // to it and each attempt resumes from just before the flipped instruction first ran
+        self.reset_program();
+        let mut checkpoints = vec![];
+        while self.halt_reason().is_none() {
+            checkpoints.push(self.snapshot());
+            if self.program_step().is_err() {
+                break;
+            }
+        }
 
-        loop {
-            let active_modification = trace.pop();
+        // walk over all of the instructions going backwards and attempt to rerun the program
+        while let Some(checkpoint) = checkpoints.pop() {
+            let active = checkpoint.program_counter as usize;
+            let original = self.instructions[active].operation;
+            let flipped = match flip(original) {
+                Some(flipped) => flipped,
+                None => continue,
+            };
 
-            if let Some(active) = active_modification {
+            self.restore(&checkpoint)
+                .expect("Checkpoints come from the current run");
+            self.instructions[active].operation = flipped;
+            if self.execute().is_ok() {
                 self.reset_program();
 
-                {
-                    let instruction = &mut self.instructions[active as usize - 1];
-                    match instruction.operation {
-                        Jump(to) => instruction.operation = Nop(to),
-                        Nop(to) => instruction.operation = Jump(to),
-                        _ => {}
-                    }
-                }
-                // attempt to run the program
-                let attempt = self.execute();
-                if attempt.is_err() {
-                    let instruction = &mut self.instructions[active as usize - 1];
-                    // rollback and try again
-                    match instruction.operation {
-                        Jump(to) => instruction.operation = Nop(to),
-                        Nop(to) => instruction.operation = Jump(to),
-                        _ => {}
-                    }
-                } else {
-                    println!("Found error at {}", active);
-                    self.reset_program();
-
-                    return self;
-                }
-            } else {
                 return self;
-            };
+            }
+            // rollback and try again
+            self.instructions[active].operation = original;
         }
+
+        self.reset_program();
+        self
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::day_eight::Program;
+impl<O: Operation> Program<O> {
+    fn from_operations(operations: Vec<O>) -> Self {
+        Self {
+            acc: 0,
+            registers: [0; REGISTERS],
+            ports: Ports::default(),
+            program_counter: 0,
+            instructions: operations
+                .into_iter()
+                .map(|operation| Instruction {
+                    operation,
+                    executed: false,
+                })
+                .collect(),
+            trace: vec![0],
+            events: None,
+            visited: HashMap::new(),
+            first_runs: vec![],
+        }
+    }
 
-    #[test]
-    fn program_is_parsable() {
-        let program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
+    // like parse_from_text but for any instruction set, and reports bad lines instead of panicking
+    fn parse_operations(source: &str) -> Result<Self, Box<dyn Error>> {
+        let operations = source
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                O::parse(line).map_err(|error| format!("line {}: {}", index + 1, error).into())
+            })
+            .collect::<Result<Vec<O>, Box<dyn Error>>>()?;
 
-        assert_eq!(program.instructions.len(), 9);
+        Ok(Self::from_operations(operations))
     }
 
-    #[test]
-    fn program_is_executable() {
-        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
-        assert!(program.execute().is_err());
+    /// The program in the same plain format it is parsed from
+    fn to_text(&self) -> String {
+        self.instructions
+            .iter()
+            .map(|instruction| format!("{}\n", instruction.operation))
+            .collect()
     }
 
-    #[test]
-    fn program_a() {
-        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.txt"));
-        assert!(program.execute().is_err());
+    fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_text())
     }
 
-    #[test]
-    fn program_a_is_self_debuggable() {
-        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.txt"));
-        assert!(program.execute().is_err());
+    // puts the program back exactly as it was parsed, trace included
+    fn reset_program(&mut self) {
+        self.program_counter = 0;
+        self.trace = vec![self.program_counter];
+        self.acc = 0;
+        self.registers = [0; REGISTERS];
+        self.ports = Ports::default();
+        for instruction in self.instructions.iter_mut() {
+            instruction.executed = false;
+        }
+        if let Some(events) = self.events.as_mut() {
+            events.clear();
+        }
+        self.visited.clear();
+        self.first_runs.clear();
+    }
 
-        program = program.self_debug();
+    fn machine_state(&self) -> MachineState {
+        MachineState {
+            program_counter: self.program_counter,
+            acc: self.acc,
+            registers: self.registers,
+            input_remaining: self
+                .ports
+                .input
+                .iter()
+                .map(|(&port, queue)| (port, queue.remaining()))
+                .collect(),
+        }
+    }
 
-        assert!(program.execute().is_ok());
-        assert_eq!(program.acc, 2060);
+    // how many steps have run since the last reset
+    fn steps(&self) -> usize {
+        self.trace.len() - 1
     }
 
-    #[test]
-    fn program_a_fixed() {
-        let mut program = Program::parse_from_text(include_str!("inputs/day_eight_fixed.txt"));
-        program.execute();
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            program_counter: self.program_counter,
+            acc: self.acc,
+            registers: self.registers,
+            ports: self.ports.positions(),
+            steps: self.steps(),
+        }
+    }
 
-        assert_eq!(program.acc, 2060)
+    // only snapshots taken earlier on the current run can be restored
+    fn restore(&mut self, snapshot: &Snapshot) -> Result<(), Box<dyn Error>> {
+        if snapshot.steps > self.steps() {
+            return Err(format!(
+                "Snapshot was taken at step {} but the program is at step {}",
+                snapshot.steps,
+                self.steps()
+            )
+            .into());
+        }
+        if self.trace[snapshot.steps] != snapshot.program_counter {
+            return Err(format!(
+                "Snapshot is from a different run, step {} was on line {}",
+                snapshot.steps,
+                self.trace[snapshot.steps].saturating_add(1)
+            )
+            .into());
+        }
+
+        self.program_counter = snapshot.program_counter;
+        self.acc = snapshot.acc;
+        self.registers = snapshot.registers;
+        self.ports.rewind(&snapshot.ports);
+        while let Some(&(step, index)) = self.first_runs.last() {
+            if step < snapshot.steps {
+                break;
+            }
+            self.instructions[index].executed = false;
+            self.first_runs.pop();
+        }
+        self.trace.truncate(snapshot.steps + 1);
+        if let Some(events) = self.events.as_mut() {
+            events.retain(|event| event.step < snapshot.steps);
+        }
+        if !self.visited.is_empty() {
+            self.visited.retain(|_, step| *step < snapshot.steps);
+        }
+        Ok(())
+    }
+
+    // records a TraceEvent for every step from now on
+    fn enable_tracing(&mut self) {
+        self.events.get_or_insert_with(Vec::new);
+    }
+
+    /// The structured trace as JSON lines, empty unless tracing is enabled
+    fn trace_jsonl(&self) -> String {
+        self.events
+            .iter()
+            .flatten()
+            .map(|event| event.to_json() + "\n")
+            .collect()
+    }
+
+    fn profile(&self) -> Profile {
+        Profile::from_trace(&self.trace, self.instructions.len())
+    }
+
+    // whether running from here has already happened once, so will again and again
+    fn is_repeating(&self) -> bool {
+        if O::REPEAT_MEANS_LOOP {
+            self.instructions[self.program_counter as usize].executed
+        } else {
+            self.visited.contains_key(&self.machine_state())
+        }
+    }
+
+    // why the program can't take another step, or None if it can
+    fn halt_reason(&self) -> Option<Halt> {
+        let pc = self.program_counter;
+        let length = self.instructions.len() as isize;
+
+        if pc == length {
+            Some(Halt::Terminated)
+        } else if pc < 0 || pc > length {
+            Some(Halt::JumpOutOfBounds)
+        } else if self.is_repeating() {
+            Some(Halt::InfiniteLoop)
+        } else {
+            None
+        }
+    }
+
+    // the full outcome for a halt, the only place a stopped run copies its trace
+    fn outcome(&self, halt: Halt) -> ExecutionOutcome {
+        let (pc, acc, trace) = (self.program_counter, self.acc, self.trace.clone());
+        match halt {
+            Halt::Terminated => ExecutionOutcome::Terminated { acc, trace },
+            Halt::InfiniteLoop => ExecutionOutcome::InfiniteLoop { pc, acc, trace },
+            Halt::JumpOutOfBounds => ExecutionOutcome::JumpOutOfBounds { pc, acc, trace },
+        }
+    }
+
+    fn program_step(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(halt) = self.halt_reason() {
+            return Err(self.outcome(halt).to_string().into());
+        }
+
+        let (pc, acc_before) = (self.program_counter, self.acc);
+        let step = self.steps();
+        // a step that faults never happened, so its state is only recorded once it succeeds
+        let state = if O::REPEAT_MEANS_LOOP {
+            None
+        } else {
+            Some(self.machine_state())
+        };
+        let instruction = &mut self.instructions[pc as usize];
+        let mut cpu = Cpu {
+            acc: &mut self.acc,
+            registers: &mut self.registers,
+            ports: &mut self.ports,
+        };
+        let offset = instruction.operation.execute(&mut cpu)?;
+        self.program_counter = pc
+            .checked_add(offset)
+            .ok_or("Jumped past the end of the address space")?;
+        if let Some(state) = state {
+            self.visited.insert(state, step);
+        }
+
+        if let Some(events) = self.events.as_mut() {
+            events.push(TraceEvent {
+                step,
+                pc,
+                operation: instruction.operation,
+                acc_before,
+                acc_after: self.acc,
+            });
+        }
+
+        if !instruction.executed {
+            instruction.executed = true;
+            self.first_runs.push((step, pc as usize));
+        }
+        self.trace.push(self.program_counter);
+
+        Ok(())
+    }
+
+    /// Runs until the program stops, giving up after step_limit steps if there is one. Loops are
+    /// caught once the machine repeats a state, but a program that never does, like a counter
+    /// climbing towards overflow, needs the limit to stop in any reasonable time.
+    fn run(&mut self, step_limit: Option<usize>) -> ExecutionOutcome {
+        let mut steps = 0;
+        loop {
+            if let Some(halt) = self.halt_reason() {
+                return self.outcome(halt);
+            }
+            if Some(steps) == step_limit {
+                return ExecutionOutcome::StepLimitExceeded {
+                    steps,
+                    acc: self.acc,
+                    trace: self.trace.clone(),
+                };
+            }
+
+            if let Err(error) = self.program_step() {
+                return ExecutionOutcome::Fault {
+                    pc: self.program_counter,
+                    message: error.to_string(),
+                    acc: self.acc,
+                    trace: self.trace.clone(),
+                };
+            }
+            steps += 1;
+        }
+    }
+
+    // succeeds only when the program runs off exactly the end of its instructions
+    fn execute(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.run(None) {
+            ExecutionOutcome::Terminated { .. } => Ok(()),
+            outcome => Err(outcome.to_string().into()),
+        }
+    }
+}
+
+/// A single instruction swap that makes the program terminate, `index` is zero based
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Repair {
+    index: usize,
+    replacement: Op,
+}
+
+// where control goes after an instruction, the program's length standing in for termination
+fn successor(index: usize, operation: Op) -> isize {
+    match operation {
+        Jump(offset) => (index as isize).saturating_add(offset),
+        Nop(_) | Acc(_) => index as isize + 1,
+    }
+}
+
+// jmp and nop swap places, acc has nothing to flip to
+fn flip(operation: Op) -> Option<Op> {
+    match operation {
+        Jump(offset) => Some(Nop(offset)),
+        Nop(offset) => Some(Jump(offset)),
+        Acc(_) => None,
+    }
+}
+
+/// The control flow of a program as a graph. Nodes are instruction indexes with one extra node at
+/// the program's length for termination, jumps that leave the program have no edge at all.
+#[derive(Debug)]
+struct ControlFlowGraph {
+    successors: Vec<Option<usize>>,
+    predecessors: Vec<Vec<usize>>,
+}
+
+impl ControlFlowGraph {
+    fn from_program(program: &Program) -> Self {
+        let length = program.instructions.len();
+        let mut successors = vec![None; length];
+        let mut predecessors = vec![vec![]; length + 1];
+
+        for (index, instruction) in program.instructions.iter().enumerate() {
+            let next = successor(index, instruction.operation);
+            if next >= 0 && next as usize <= length {
+                successors[index] = Some(next as usize);
+                predecessors[next as usize].push(index);
+            }
+        }
+
+        Self {
+            successors,
+            predecessors,
+        }
+    }
+
+    fn termination(&self) -> usize {
+        self.successors.len()
+    }
+
+    /// Marks every node that runs off the end of the program, found by walking the edges
+    /// backwards from termination
+    fn reaches_termination(&self) -> Vec<bool> {
+        let mut reaches = vec![false; self.termination() + 1];
+        let mut stack = vec![self.termination()];
+        reaches[self.termination()] = true;
+
+        while let Some(node) = stack.pop() {
+            for &previous in self.predecessors[node].iter() {
+                if !reaches[previous] {
+                    reaches[previous] = true;
+                    stack.push(previous);
+                }
+            }
+        }
+
+        reaches
+    }
+
+    // the instructions run from the start until one repeats or control leaves the program
+    fn executed_path(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.termination()];
+        let mut path = vec![];
+        let mut node = 0;
+
+        while node < self.termination() && !visited[node] {
+            visited[node] = true;
+            path.push(node);
+            match self.successors[node] {
+                Some(next) => node = next,
+                None => break,
+            }
+        }
+
+        path
+    }
+}
+
+impl Program {
+    /// Every single jmp/nop flip that makes the program terminate, found in linear time.
+    ///
+    /// Only instructions that actually run can change the outcome, and a flip works exactly when
+    /// it sends control into the set of instructions that already reach termination. None of the
+    /// executed instructions are in that set when the program doesn't terminate, so the new path
+    /// can never come back through the flipped instruction. A program that already terminates
+    /// needs no repair and gets none.
+    fn find_repairs(&self) -> Vec<Repair> {
+        let graph = ControlFlowGraph::from_program(self);
+        let reaches = graph.reaches_termination();
+        if reaches[0] {
+            return vec![];
+        }
+
+        graph
+            .executed_path()
+            .into_iter()
+            .filter_map(|index| {
+                let replacement = flip(self.instructions[index].operation)?;
+                let next = successor(index, replacement);
+                let terminates =
+                    next >= 0 && next as usize <= graph.termination() && reaches[next as usize];
+                if terminates {
+                    Some(Repair { index, replacement })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn apply_repair(&mut self, repair: &Repair) {
+        self.instructions[repair.index].operation = repair.replacement;
+    }
+
+    // a repair as a patch that remembers what it replaced
+    fn repair_patch(&self, repair: &Repair) -> Patch {
+        let mut patch = Patch::new();
+        patch.substitutions.push(Substitution {
+            index: repair.index,
+            original: self.instructions[repair.index].operation,
+            replacement: repair.replacement,
+        });
+        patch
+    }
+}
+
+/// One instruction swapped for another, `index` is zero based
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Substitution<O = Op> {
+    index: usize,
+    original: O,
+    replacement: O,
+}
+
+/// A set of instruction substitutions kept apart from the program they fix. Saved as one
+/// substitution per line, eg: `211: jmp -31 -> nop -31`.
+#[derive(Debug, Clone, PartialEq)]
+struct Patch<O = Op> {
+    substitutions: Vec<Substitution<O>>,
+}
+
+impl<O: Operation> Patch<O> {
+    fn new() -> Self {
+        Self {
+            substitutions: vec![],
+        }
+    }
+
+    fn substitute(
+        &mut self,
+        index: usize,
+        original: O,
+        replacement: O,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.replacement(index).is_some() {
+            return Err(format!("Line {} is already patched", index + 1).into());
+        }
+        self.substitutions.push(Substitution {
+            index,
+            original,
+            replacement,
+        });
+        Ok(())
+    }
+
+    fn replacement(&self, index: usize) -> Option<O> {
+        self.substitutions
+            .iter()
+            .find(|substitution| substitution.index == index)
+            .map(|substitution| substitution.replacement)
+    }
+
+    fn to_text(&self) -> String {
+        self.substitutions
+            .iter()
+            .map(|substitution| {
+                format!(
+                    "{}: {} -> {}\n",
+                    substitution.index + 1,
+                    substitution.original,
+                    substitution.replacement
+                )
+            })
+            .collect()
+    }
+
+    fn parse(source: &str) -> Result<Self, Box<dyn Error>> {
+        let mut patch = Self::new();
+        for line in source.lines().filter(|line| !line.trim().is_empty()) {
+            let (number, swap) = line
+                .split_once(':')
+                .ok_or_else(|| format!("Missing line number in {:?}", line))?;
+            let (original, replacement) = swap
+                .split_once("->")
+                .ok_or_else(|| format!("Missing -> in {:?}", line))?;
+            let index = match number.trim().parse::<usize>() {
+                Ok(number) if number > 0 => number - 1,
+                _ => return Err(format!("Invalid line number {:?}", number).into()),
+            };
+            patch.substitute(index, O::parse(original)?, O::parse(replacement)?)?;
+        }
+
+        Ok(patch)
+    }
+
+    // a substitution only applies over the instruction it was made for
+    fn check(&self, index: usize, found: O) -> Result<(), Box<dyn Error>> {
+        match self
+            .substitutions
+            .iter()
+            .find(|substitution| substitution.index == index)
+        {
+            Some(substitution) if substitution.original != found => Err(format!(
+                "Line {} is {} but the patch expects {}",
+                index + 1,
+                found,
+                substitution.original
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_fits(&self, length: usize) -> Result<(), Box<dyn Error>> {
+        match self
+            .substitutions
+            .iter()
+            .find(|substitution| substitution.index >= length)
+        {
+            Some(substitution) => Err(format!(
+                "Line {} is past the end of the program",
+                substitution.index + 1
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    fn check_program(&self, program: &Program<O>) -> Result<(), Box<dyn Error>> {
+        self.check_fits(program.instructions.len())?;
+        for (index, instruction) in program.instructions.iter().enumerate() {
+            self.check(index, instruction.operation)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the patch into the program for good
+    fn apply(&self, program: &mut Program<O>) -> Result<(), Box<dyn Error>> {
+        self.check_program(program)?;
+        for substitution in self.substitutions.iter() {
+            program.instructions[substitution.index].operation = substitution.replacement;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the patched lines of the source the program was parsed from, everything else is
+    /// left exactly as it was. Blank lines aren't instructions so they aren't counted.
+    fn apply_to_source(&self, source: &str) -> Result<String, Box<dyn Error>> {
+        let mut index = 0;
+        let mut output = String::new();
+        for line in source.split_inclusive('\n') {
+            let text = line.trim_end_matches(['\r', '\n']);
+            if text.trim().is_empty() {
+                output += line;
+                continue;
+            }
+
+            match self.replacement(index) {
+                Some(replacement) => {
+                    self.check(index, O::parse(text)?)?;
+                    output += &replacement.to_string();
+                    output += &line[text.len()..];
+                }
+                None => output += line,
+            }
+            index += 1;
+        }
+
+        self.check_fits(index)?;
+        Ok(output)
+    }
+}
+
+/// A program seen through a patch, the program itself is never changed
+struct Patched<'a, O = Op> {
+    program: &'a Program<O>,
+    patch: &'a Patch<O>,
+}
+
+impl<'a, O: Operation> Patched<'a, O> {
+    fn new(program: &'a Program<O>, patch: &'a Patch<O>) -> Result<Self, Box<dyn Error>> {
+        patch.check_program(program)?;
+        Ok(Self { program, patch })
+    }
+
+    fn operation(&self, index: usize) -> O {
+        self.patch
+            .replacement(index)
+            .unwrap_or(self.program.instructions[index].operation)
+    }
+
+    /// Runs the patched instructions from a fresh start on a machine of their own
+    fn run(&self, step_limit: Option<usize>) -> ExecutionOutcome {
+        let operations = (0..self.program.instructions.len())
+            .map(|index| self.operation(index))
+            .collect();
+        Program::from_operations(operations).run(step_limit)
+    }
+}
+
+/// An assembler error on a one based source line
+#[derive(Debug, PartialEq)]
+struct AssembleError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssembleError {}
+
+/// Assembles boot code written with labels and comments.
+///
+/// ```text
+/// # comments start with # or ;
+/// start:
+///     acc +1
+///     nop +0      ; numeric offsets still work
+///     jmp start   ; labels become offsets relative to this instruction
+/// ```
+fn assemble(source: &str) -> Result<Program, AssembleError> {
+    let error = |line: usize, message: String| AssembleError { line, message };
+
+    // first pass finds where each label points, second pass resolves operands
+    let mut labels = HashMap::new();
+    let mut statements = vec![];
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut text = line.split(['#', ';']).next().unwrap_or("").trim();
+
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if label.is_empty() || !label.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(error(line_number, format!("invalid label {:?}", label)));
+            }
+            if labels
+                .insert(label, statements.len() as isize + 1)
+                .is_some()
+            {
+                return Err(error(
+                    line_number,
+                    format!("label {:?} is defined twice", label),
+                ));
+            }
+            text = text[colon + 1..].trim();
+        }
+
+        if !text.is_empty() {
+            statements.push((line_number, text));
+        }
+    }
+
+    let mut operations = vec![];
+    for (index, &(line_number, text)) in statements.iter().enumerate() {
+        let line = index as isize + 1;
+        let mut words = text.split_whitespace();
+        let mnemonic = words.next().unwrap_or("");
+        let operand = words
+            .next()
+            .ok_or_else(|| error(line_number, format!("{} needs an operand", mnemonic)))?;
+        if let Some(extra) = words.next() {
+            return Err(error(line_number, format!("unexpected {:?}", extra)));
+        }
+
+        let value = match (operand.parse::<isize>(), labels.get(operand)) {
+            (Ok(value), _) => value,
+            (Err(_), Some(&target)) if mnemonic != "acc" => target - line,
+            (Err(_), Some(_)) => {
+                return Err(error(line_number, "acc can't take a label".to_string()))
+            }
+            (Err(_), None) => {
+                return Err(error(line_number, format!("unknown operand {:?}", operand)))
+            }
+        };
+
+        operations.push(match mnemonic {
+            "jmp" => Jump(value),
+            "nop" => Nop(value),
+            "acc" => Acc(value),
+            _ => {
+                return Err(error(
+                    line_number,
+                    format!("unknown instruction {:?}", mnemonic),
+                ))
+            }
+        });
+    }
+
+    Ok(Program::from_operations(operations))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn parse(source: &str) -> Option<Self> {
+        match source {
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            ">" => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            _ => None,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+
+    fn holds(self, left: isize, right: isize) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Breakpoint {
+    // stop before the instruction on this line runs
+    Line(isize),
+    // stop as soon as the accumulator satisfies the comparison
+    Accumulator(Comparison, isize),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Line(line) => write!(f, "line {}", line),
+            Breakpoint::Accumulator(comparison, value) => {
+                write!(f, "acc {} {}", comparison.symbol(), value)
+            }
+        }
+    }
+}
+
+const DEBUGGER_HELP: &str = "\
+step [n]                  run the next n instructions, 1 by default
+continue                  run until a breakpoint, a loop or the end of the program
+break <line>              stop before the instruction on <line> runs
+break acc <op> <value>    stop once acc compares true, op is one of == != < <= > >=
+delete <n>                remove the nth breakpoint
+breakpoints               list breakpoints
+print acc|pc              show the accumulator or the zero based program counter
+list [n]                  show n instructions either side of the program counter
+back [n]                  undo the last n steps, 1 by default
+reset                     start the program again, breakpoints are kept
+quit                      leave the debugger";
+
+/// Steps through a program under the control of a user, stopping at breakpoints
+#[derive(Debug)]
+struct Debugger {
+    program: Program,
+    breakpoints: Vec<Breakpoint>,
+    history: History,
+}
+
+// how many steps the debugger can go back
+const HISTORY_LIMIT: usize = 1000;
+
+impl Debugger {
+    fn new(program: Program) -> Self {
+        Self {
+            program,
+            breakpoints: vec![],
+            history: History::new(HISTORY_LIMIT),
+        }
+    }
+
+    // the one based line of the next instruction to run
+    fn line(&self) -> isize {
+        self.program.program_counter.saturating_add(1)
+    }
+
+    fn step(&mut self) -> Result<(), Box<dyn Error>> {
+        let snapshot = self.program.snapshot();
+        self.program.program_step()?;
+        self.history.push(snapshot);
+        Ok(())
+    }
+
+    fn step_back(&mut self) -> Result<(), Box<dyn Error>> {
+        let snapshot = self.history.pop().ok_or("No earlier steps to go back to")?;
+        self.program.restore(&snapshot)
+    }
+
+    fn hit_breakpoint(&self) -> Option<(usize, Breakpoint)> {
+        self.breakpoints
+            .iter()
+            .enumerate()
+            .find(|(_, breakpoint)| match **breakpoint {
+                Breakpoint::Line(line) => line == self.line(),
+                Breakpoint::Accumulator(comparison, value) => {
+                    comparison.holds(self.program.acc, value)
+                }
+            })
+            .map(|(index, &breakpoint)| (index + 1, breakpoint))
+    }
+
+    // always takes at least one step so continuing from a breakpoint doesn't stop on it again
+    fn continue_execution(&mut self) -> Result<String, Box<dyn Error>> {
+        loop {
+            self.step()?;
+            if let Some(halt @ Halt::Terminated) = self.program.halt_reason() {
+                return Ok(self.program.outcome(halt).to_string());
+            }
+            if let Some((index, breakpoint)) = self.hit_breakpoint() {
+                return Ok(format!(
+                    "Breakpoint {} ({}) hit at line {}",
+                    index,
+                    breakpoint,
+                    self.line()
+                ));
+            }
+        }
+    }
+
+    // the instructions around the program counter, => marks the next one to run and * a breakpoint
+    fn list(&self, radius: isize) -> String {
+        let current = self.line();
+        let first = current.saturating_sub(radius).max(1);
+        let last = current
+            .saturating_add(radius)
+            .min(self.program.instructions.len() as isize);
+
+        (first..=last)
+            .map(|line| {
+                let marker = if line == current {
+                    "=>"
+                } else if self.breakpoints.contains(&Breakpoint::Line(line)) {
+                    " *"
+                } else {
+                    "  "
+                };
+                let operation = self.program.instructions[line as usize - 1].operation;
+                format!("{} {}", marker, Program::listing(line as usize, operation))
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn run_command(&mut self, command: &str) -> Result<String, Box<dyn Error>> {
+        let words = command.split_whitespace().collect::<Vec<&str>>();
+
+        match words.as_slice() {
+            ["step"] | ["s"] => {
+                self.step()?;
+                Ok(self.list(0))
+            }
+            ["step", count] | ["s", count] => {
+                for _ in 0..count.parse::<usize>()? {
+                    self.step()?;
+                }
+                Ok(self.list(0))
+            }
+            ["back"] => {
+                self.step_back()?;
+                Ok(self.list(0))
+            }
+            ["back", count] => {
+                for _ in 0..count.parse::<usize>()? {
+                    self.step_back()?;
+                }
+                Ok(self.list(0))
+            }
+            ["continue"] | ["c"] => self.continue_execution(),
+            ["break", "acc", comparison, value] => {
+                let comparison = Comparison::parse(comparison)
+                    .ok_or_else(|| format!("Unknown comparison {:?}", comparison))?;
+                self.breakpoints
+                    .push(Breakpoint::Accumulator(comparison, value.parse()?));
+                Ok(format!("Breakpoint {} set", self.breakpoints.len()))
+            }
+            ["break", line] => {
+                self.breakpoints.push(Breakpoint::Line(line.parse()?));
+                Ok(format!("Breakpoint {} set", self.breakpoints.len()))
+            }
+            ["delete", index] => {
+                let index = index.parse::<usize>()?;
+                if index == 0 || index > self.breakpoints.len() {
+                    return Err(format!("No breakpoint {}", index).into());
+                }
+                Ok(format!("Deleted {}", self.breakpoints.remove(index - 1)))
+            }
+            ["breakpoints"] => Ok(self
+                .breakpoints
+                .iter()
+                .enumerate()
+                .map(|(index, breakpoint)| format!("{}: {}", index + 1, breakpoint))
+                .collect::<Vec<String>>()
+                .join("\n")),
+            ["print", "acc"] | ["p", "acc"] => Ok(self.program.acc.to_string()),
+            ["print", "pc"] | ["p", "pc"] => Ok(format!(
+                "{} (line {})",
+                self.program.program_counter,
+                self.line()
+            )),
+            ["list"] | ["l"] => Ok(self.list(3)),
+            ["list", radius] | ["l", radius] => Ok(self.list(radius.parse()?)),
+            ["reset"] => {
+                self.program.reset_program();
+                self.history.clear();
+                Ok("Program reset".to_string())
+            }
+            ["help"] => Ok(DEBUGGER_HELP.to_string()),
+            _ => Err(format!("Unknown command {:?}, try help", command).into()),
+        }
+    }
+
+    fn run_shell(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        write!(output, "(debug) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            match line.trim() {
+                "" => {}
+                "quit" | "q" => break,
+                command => match self.run_command(command) {
+                    Ok(answer) => writeln!(output, "{}", answer)?,
+                    Err(error) => writeln!(output, "error: {}", error)?,
+                },
+            }
+            write!(output, "(debug) ")?;
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Loads a boot program and debugs it interactively over stdin
+pub fn debugger_shell(program_path: &str) -> Result<(), Box<dyn Error>> {
+    let source = fs::read_to_string(program_path)?;
+    let program = Program::parse_operations(&source)
+        .map_err(|error| format!("Unable to load {}: {}", program_path, error))?;
+    let mut debugger = Debugger::new(program);
+
+    println!(
+        "Loaded {} instructions from {}, type help for commands",
+        debugger.program.instructions.len(),
+        program_path
+    );
+    debugger.run_shell(io::stdin().lock(), io::stdout())?;
+    Ok(())
+}
+
+/// Relative weights for each opcode in a generated program
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct OpcodeMix {
+    acc: u64,
+    jmp: u64,
+    nop: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct GeneratorConfig {
+    size: usize,
+    mix: OpcodeMix,
+    // acc operands are drawn from -max_acc to max_acc
+    max_acc: isize,
+}
+
+impl Default for GeneratorConfig {
+    // roughly the shape of the puzzle input
+    fn default() -> Self {
+        Self {
+            size: 50,
+            mix: OpcodeMix {
+                acc: 5,
+                jmp: 3,
+                nop: 2,
+            },
+            max_acc: 50,
+        }
+    }
+}
+
+// xorshift, plenty for test programs and no dependency needed
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift only ever returns zero from a zero state, so that one seed is swapped out
+        const MIXER: u64 = 0x9E37_79B9_7F4A_7C15;
+        match seed ^ MIXER {
+            0 => Self(MIXER),
+            state => Self(state),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    // inclusive of both ends
+    fn between(&mut self, low: isize, high: isize) -> isize {
+        low + self.below((high - low) as u64 + 1) as isize
+    }
+}
+
+/// Makes random boot programs where every jmp and nop points somewhere inside the program or
+/// just past its end
+#[derive(Debug)]
+struct ProgramGenerator {
+    config: GeneratorConfig,
+    rng: Rng,
+}
+
+impl ProgramGenerator {
+    fn new(config: GeneratorConfig, seed: u64) -> Result<Self, Box<dyn Error>> {
+        let OpcodeMix { acc, jmp, nop } = config.mix;
+        if config.size == 0 {
+            return Err("Generated programs need at least one instruction".into());
+        }
+        match acc
+            .checked_add(jmp)
+            .and_then(|total| total.checked_add(nop))
+        {
+            Some(0) => return Err("The opcode mix needs at least one non zero weight".into()),
+            None => return Err("The opcode mix weights add up to more than a u64".into()),
+            Some(_) => {}
+        }
+        // keeps the whole -max_acc to max_acc range countable in an isize
+        if config.max_acc < 0 || config.max_acc > isize::MAX / 2 {
+            return Err(format!("max_acc must be between 0 and {}", isize::MAX / 2).into());
+        }
+
+        Ok(Self {
+            config,
+            rng: Rng::new(seed),
+        })
+    }
+
+    fn operation(&mut self, index: usize) -> Op {
+        let OpcodeMix { acc, jmp, nop } = self.config.mix;
+        // any line of the program or just past its end
+        let (lowest, highest) = (-(index as isize), (self.config.size - index) as isize);
+        let roll = self.rng.below(acc + jmp + nop);
+        if roll < acc {
+            Acc(self.rng.between(-self.config.max_acc, self.config.max_acc))
+        } else if roll < acc + jmp {
+            // jmp +0 can only ever loop on the spot
+            loop {
+                match self.rng.between(lowest, highest) {
+                    0 => continue,
+                    offset => return Jump(offset),
+                }
+            }
+        } else {
+            Nop(self.rng.between(lowest, highest))
+        }
+    }
+
+    fn operations(&mut self) -> Vec<Op> {
+        (0..self.config.size)
+            .map(|index| self.operation(index))
+            .collect()
+    }
+
+    fn generate(&mut self) -> Program {
+        Program::from_operations(self.operations())
+    }
+
+    /// A program that loops because exactly one jmp or nop on its path was flipped, along with
+    /// the only repair that makes it terminate again. Each attempt is a fresh random program, None
+    /// if none of them worked out.
+    fn generate_corrupted(&mut self, attempts: usize) -> Option<(Program, Repair)> {
+        if self.config.mix.jmp == 0 && self.config.mix.nop == 0 {
+            return None;
+        }
+
+        for _ in 0..attempts {
+            let operations = self.operations();
+            let mut program = Program::from_operations(operations.clone());
+            if program.execute().is_err() {
+                continue;
+            }
+
+            let path = &program.trace[..program.trace.len() - 1];
+            let start = self.rng.below(path.len() as u64) as usize;
+            for &pc in path[start..].iter().chain(path[..start].iter()) {
+                let index = pc as usize;
+                let flipped = match flip(operations[index]) {
+                    Some(flipped) => flipped,
+                    None => continue,
+                };
+
+                let mut corrupted = operations.clone();
+                corrupted[index] = flipped;
+                let mut corrupted = Program::from_operations(corrupted);
+                if !matches!(corrupted.run(None), ExecutionOutcome::InfiniteLoop { .. }) {
+                    continue;
+                }
+                let repair = Repair {
+                    index,
+                    replacement: operations[index],
+                };
+                if corrupted.find_repairs() == vec![repair] {
+                    corrupted.reset_program();
+                    return Some((corrupted, repair));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Throws arbitrary text at everything that reads programs, patches or debugger commands. None
+/// of it may panic, however malformed the text. Kept free of file and terminal IO so it can be
+/// driven by `cargo fuzz run day_eight` from the fuzz directory.
+pub fn fuzz_text(text: &str) {
+    // bounds the work any one input can cause
+    const STEP_LIMIT: usize = 10_000;
+
+    if let Ok(mut program) = Program::<Op>::parse_operations(text) {
+        program.enable_tracing();
+        program.run(Some(STEP_LIMIT));
+        program.trace_jsonl();
+        program.profile().to_string();
+        program.disassemble();
+        if let Ok(mut reassembled) = assemble(&program.to_assembly()) {
+            reassembled.run(Some(STEP_LIMIT));
+        }
+        for repair in program.find_repairs() {
+            let patch = program.repair_patch(&repair);
+            if let Ok(patched) = Patched::new(&program, &patch) {
+                patched.run(Some(STEP_LIMIT));
+            }
+            let _ = patch.apply_to_source(text);
+        }
+        program.self_debug();
+    }
+
+    if let Ok(mut program) = Program::<ExtendedOp>::parse_operations(text) {
+        program.ports.feed(0, text.bytes().map(isize::from));
+        program.run(Some(STEP_LIMIT));
+    }
+
+    if let Ok(mut program) = assemble(text) {
+        let mut debugger = Debugger::new(Program::from_operations(
+            program
+                .instructions
+                .iter()
+                .map(|instruction| instruction.operation)
+                .collect(),
+        ));
+        program.run(Some(STEP_LIMIT));
+        for command in text.lines().take(100) {
+            let _ = debugger.run_command(command);
+        }
+    }
+
+    if let Ok(patch) = Patch::<Op>::parse(text) {
+        let _ = patch.apply_to_source(text);
+        patch.to_text();
+    }
+    if let Ok(patch) = Patch::<ExtendedOp>::parse(text) {
+        patch.to_text();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::day_eight::{
+        assemble, fuzz_text, Breakpoint, Comparison, Debugger, ExecutionOutcome, ExtendedOp,
+        GeneratorConfig, Halt, History, HotLoop, Op, OpcodeMix, Operation, Patch, Patched, Program,
+        ProgramGenerator, Repair, Rng,
+    };
+    use indoc::indoc;
+
+    #[test]
+    fn program_is_parsable() {
+        let program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
+
+        assert_eq!(program.instructions.len(), 9);
+    }
+
+    #[test]
+    fn program_is_executable() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
+        assert!(program.execute().is_err());
+    }
+
+    #[test]
+    fn program_a() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.txt"));
+        assert!(program.execute().is_err());
+    }
+
+    #[test]
+    fn program_a_is_self_debuggable() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.txt"));
+        assert!(program.execute().is_err());
+
+        program = program.self_debug();
+
+        assert!(program.execute().is_ok());
+        assert_eq!(program.acc, 2060);
+    }
+
+    #[test]
+    fn program_a_fixed() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight_fixed.txt"));
+        program.execute();
+
+        assert_eq!(program.acc, 2060)
+    }
+
+    #[test]
+    fn debugger_can_step_and_inspect_state() {
+        let mut debugger = Debugger::new(Program::parse_from_text(include_str!(
+            "inputs/day_eight.test.txt"
+        )));
+
+        assert_eq!(debugger.run_command("step").unwrap(), "=>    2  acc +1");
+        assert_eq!(debugger.run_command("step 2").unwrap(), "=>    7  acc +1");
+        assert_eq!(debugger.run_command("print acc").unwrap(), "1");
+        assert_eq!(debugger.run_command("print pc").unwrap(), "6 (line 7)");
+        assert_eq!(
+            debugger.run_command("list 2").unwrap(),
+            [
+                "      5  jmp -3    ; -> 2",
+                "      6  acc -99",
+                "=>    7  acc +1",
+                "      8  jmp -4    ; -> 4",
+                "      9  acc +6",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn debugger_stops_at_breakpoints() {
+        let mut debugger = Debugger::new(Program::parse_from_text(include_str!(
+            "inputs/day_eight.test.txt"
+        )));
+
+        debugger.run_command("break 4").unwrap();
+        debugger.run_command("break acc >= 5").unwrap();
+        assert_eq!(
+            debugger.breakpoints,
+            vec![
+                Breakpoint::Line(4),
+                Breakpoint::Accumulator(Comparison::GreaterOrEqual, 5)
+            ]
+        );
+        assert_eq!(
+            debugger.run_command("breakpoints").unwrap(),
+            "1: line 4\n2: acc >= 5"
+        );
+
+        assert_eq!(
+            debugger.run_command("continue").unwrap(),
+            "Breakpoint 1 (line 4) hit at line 4"
+        );
+        assert_eq!(debugger.run_command("print acc").unwrap(), "2");
+        assert_eq!(
+            debugger.run_command("continue").unwrap(),
+            "Breakpoint 2 (acc >= 5) hit at line 5"
+        );
+        // accumulator breakpoints keep firing for as long as they hold
+        assert_eq!(
+            debugger.run_command("continue").unwrap(),
+            "Breakpoint 2 (acc >= 5) hit at line 2"
+        );
+
+        debugger.run_command("delete 2").unwrap();
+        assert_eq!(
+            debugger.run_command("continue").unwrap_err().to_string(),
+            "Line 2 has already run, the program is looping"
+        );
+
+        debugger.run_command("reset").unwrap();
+        assert_eq!(
+            debugger.run_command("continue").unwrap(),
+            "Breakpoint 1 (line 4) hit at line 4"
+        );
+    }
+
+    #[test]
+    fn debugger_reports_termination() {
+        let mut debugger = Debugger::new(Program::parse_from_text("nop +0\nacc +7"));
+
+        assert_eq!(
+            debugger.run_command("c").unwrap(),
+            "The program terminated with acc 7"
+        );
+        assert!(debugger.run_command("step").is_err());
+    }
+
+    #[test]
+    fn debugger_shell_reads_commands_until_quit() {
+        let mut debugger = Debugger::new(Program::parse_from_text(include_str!(
+            "inputs/day_eight.test.txt"
+        )));
+        let mut output = vec![];
+        debugger
+            .run_shell("s\np acc\nfly\nq\ns\n".as_bytes(), &mut output)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "(debug) =>    2  acc +1\n(debug) 0\n(debug) error: Unknown command \"fly\", try help\n(debug) "
+        );
+    }
+
+    #[test]
+    fn programs_can_be_disassembled() {
+        let program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
+
+        assert_eq!(
+            program.disassemble().lines().take(5).collect::<Vec<&str>>(),
+            vec![
+                "   1  nop +0    ; would jump -> 1",
+                "   2  acc +1",
+                "   3  jmp +4    ; -> 7",
+                "   4  acc +3",
+                "   5  jmp -3    ; -> 2",
+            ]
+        );
+        assert_eq!(
+            program.to_text(),
+            include_str!("inputs/day_eight.test.txt").to_string() + "\n"
+        );
+    }
+
+    #[test]
+    fn programs_can_be_assembled_from_labels() {
+        let program = assemble(indoc! {"
+            # count to three then stop
+            start: acc +1
+            loop:
+                acc +1   ; second bump
+                nop start
+                jmp end
+                jmp loop
+            end:
+        "})
+        .unwrap();
+
+        assert_eq!(
+            program
+                .instructions
+                .iter()
+                .map(|instruction| instruction.operation)
+                .collect::<Vec<Op>>(),
+            vec![
+                Op::Acc(1),
+                Op::Acc(1),
+                Op::Nop(-2),
+                Op::Jump(2),
+                Op::Jump(-3)
+            ]
+        );
+        assert_eq!(
+            program.to_text(),
+            "acc +1\nacc +1\nnop -2\njmp +2\njmp -3\n"
+        );
+    }
+
+    #[test]
+    fn assembler_errors_name_the_line() {
+        let error = |source| assemble(source).unwrap_err().to_string();
+
+        assert_eq!(
+            error("acc +1\njmp nowhere"),
+            "line 2: unknown operand \"nowhere\""
+        );
+        assert_eq!(error("a:\nacc a"), "line 2: acc can't take a label");
+        assert_eq!(
+            error("a: nop +0\na: nop +0"),
+            "line 2: label \"a\" is defined twice"
+        );
+        assert_eq!(error("\n\nmul +2"), "line 3: unknown instruction \"mul\"");
+        assert_eq!(error("jmp"), "line 1: jmp needs an operand");
+        assert_eq!(error("jmp +1 +2"), "line 1: unexpected \"+2\"");
+    }
+
+    #[test]
+    fn assembly_round_trips_through_labels() {
+        let program = Program::parse_from_text(include_str!("inputs/day_eight.txt"));
+        let source = program.to_assembly();
+
+        assert!(source.contains(":\n"));
+        assert_eq!(assemble(&source).unwrap().to_text(), program.to_text());
+    }
+
+    #[test]
+    fn patched_programs_can_be_saved() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.txt"));
+        program.instructions[210].operation = Op::Nop(-31);
+
+        let path = std::env::temp_dir().join("day_eight_patched.txt");
+        program.save(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap().trim_end(),
+            include_str!("inputs/day_eight_fixed.txt").trim_end()
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn execution_reports_infinite_loops() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
+
+        assert_eq!(
+            program.run(None),
+            ExecutionOutcome::InfiniteLoop {
+                pc: 1,
+                acc: 5,
+                trace: vec![0, 1, 2, 6, 7, 3, 4, 1],
+            }
+        );
+        assert_eq!(program.halt_reason(), Some(Halt::InfiniteLoop));
+    }
+
+    #[test]
+    fn execution_terminates_exactly_at_the_end() {
+        let mut program = Program::parse_from_text("nop +0\nacc +3\njmp +1");
+        let outcome = program.run(None);
+
+        assert_eq!(
+            outcome,
+            ExecutionOutcome::Terminated {
+                acc: 3,
+                trace: vec![0, 1, 2, 3],
+            }
+        );
+        assert_eq!(outcome.to_string(), "The program terminated with acc 3");
+    }
+
+    #[test]
+    fn execution_reports_jumps_out_of_bounds() {
+        let mut program = Program::parse_from_text("acc +1\njmp +5\nacc +1");
+        let outcome = program.run(None);
+        assert_eq!(
+            outcome,
+            ExecutionOutcome::JumpOutOfBounds {
+                pc: 6,
+                acc: 1,
+                trace: vec![0, 1, 6],
+            }
+        );
+        assert!(program.execute().is_err());
+
+        let mut program = Program::parse_from_text("acc +1\njmp -2");
+        assert!(matches!(
+            program.run(None),
+            ExecutionOutcome::JumpOutOfBounds { pc: -1, acc: 1, .. }
+        ));
+        assert_eq!(program.halt_reason(), Some(Halt::JumpOutOfBounds));
+        assert!(program.program_step().is_err());
+    }
+
+    #[test]
+    fn execution_can_be_limited() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
+        let outcome = program.run(Some(3));
+
+        assert_eq!(
+            outcome,
+            ExecutionOutcome::StepLimitExceeded {
+                steps: 3,
+                acc: 1,
+                trace: vec![0, 1, 2, 6],
+            }
+        );
+        assert_eq!(outcome.acc(), 1);
+        assert_eq!(outcome.trace(), &[0, 1, 2, 6]);
+        assert_eq!(program.run(None).acc(), 5);
+    }
+
+    #[test]
+    fn program_counter_starts_at_zero() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
+        assert_eq!(program.program_counter, 0);
+        assert_eq!(program.trace, vec![0]);
+
+        program.program_step().unwrap();
+        assert_eq!(program.program_counter, 1);
+        assert!(program.instructions[0].executed);
+        assert!(!program.instructions[1].executed);
+    }
+
+    #[test]
+    fn trace_is_identical_across_resets() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
+        let first = program.run(None);
+
+        program.reset_program();
+        assert_eq!(program.program_counter, 0);
+        assert_eq!(program.trace, vec![0]);
+        assert!(program.instructions.iter().all(|i| !i.executed));
+
+        let second = program.run(None);
+        assert_eq!(first, second);
+        assert_eq!(first.trace(), &[0, 1, 2, 6, 7, 3, 4, 1]);
+    }
+
+    #[test]
+    fn self_debug_is_repeatable_after_a_reset() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.txt"));
+        assert!(program.execute().is_err());
+        let trace = program.trace.clone();
+
+        program.reset_program();
+        assert!(program.execute().is_err());
+        assert_eq!(program.trace, trace);
+
+        program = program.self_debug();
+        assert!(matches!(program.instructions[210].operation, Op::Nop(-31)));
+        assert!(program.execute().is_ok());
+        assert_eq!(program.acc, 2060);
+    }
+
+    #[test]
+    fn repairs_are_found_without_brute_force() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
+        let repairs = program.find_repairs();
+
+        assert_eq!(
+            repairs,
+            vec![Repair {
+                index: 7,
+                replacement: Op::Nop(-4)
+            }]
+        );
+        program.apply_repair(&repairs[0]);
+        assert!(program.execute().is_ok());
+        assert_eq!(program.acc, 8);
+        program.reset_program();
+        assert!(program.find_repairs().is_empty());
+    }
+
+    #[test]
+    fn every_valid_repair_is_reported() {
+        // the nop on line 2 and the jmps on lines 3 and 5 can each be flipped to reach the end
+        let program = assemble(indoc! {"
+            acc +1
+            nop end
+            jmp start
+            jmp +2
+            start: jmp -4
+            end:
+        "})
+        .unwrap();
+
+        assert_eq!(
+            program.find_repairs(),
+            vec![
+                Repair {
+                    index: 1,
+                    replacement: Op::Jump(4)
+                },
+                Repair {
+                    index: 2,
+                    replacement: Op::Nop(2)
+                },
+                Repair {
+                    index: 4,
+                    replacement: Op::Nop(-4)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn production_repair_matches_self_debug() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.txt"));
+        let repairs = program.find_repairs();
+
+        assert_eq!(
+            repairs,
+            vec![Repair {
+                index: 210,
+                replacement: Op::Nop(-31)
+            }]
+        );
+        program.apply_repair(&repairs[0]);
+        assert!(program.execute().is_ok());
+        assert_eq!(program.acc, 2060);
+    }
+
+    #[test]
+    fn operations_parse_and_print() {
+        assert_eq!(Op::parse("jmp -4").unwrap(), Op::Jump(-4));
+        assert!(Op::parse("jmp").is_err());
+        assert!(Op::parse("mul +2").is_err());
+        assert!(Op::parse("acc four").is_err());
+
+        for line in vec![
+            "mul +3", "jz -2", "jnz +4", "lda r1", "sta r3", "add r0", "in 0", "out 2", "acc +5",
+        ] {
+            assert_eq!(ExtendedOp::parse(line).unwrap().to_string(), line);
+        }
+        assert_eq!(
+            ExtendedOp::parse("nop +0").unwrap(),
+            ExtendedOp::Base(Op::Nop(0))
+        );
+        assert!(ExtendedOp::parse("lda r4").is_err());
+        assert!(ExtendedOp::parse("out east").is_err());
+    }
+
+    #[test]
+    fn extended_programs_use_registers_and_ports() {
+        // sums 1 to n where n arrives on port 0 and the total leaves on port 1
+        let mut program = Program::<ExtendedOp>::parse_operations(indoc! {"
+            in 0
+            sta r0
+            mul +0
+            sta r1
+            lda r1
+            add r0
+            sta r1
+            lda r0
+            acc -1
+            sta r0
+            jnz -6
+            lda r1
+            out 1
+        "})
+        .unwrap();
+        program.ports.feed(0, vec![10]);
+
+        let outcome = program.run(Some(1000));
+        assert!(matches!(
+            outcome,
+            ExecutionOutcome::Terminated { acc: 55, .. }
+        ));
+        assert_eq!(program.ports.output(1), &[55]);
+        assert_eq!(program.registers, [0, 55, 0, 0]);
+
+        program.reset_program();
+        program.ports.feed(0, vec![3]);
+        program.run(None);
+        assert_eq!(program.ports.output(1), &[6]);
+    }
+
+    #[test]
+    fn extended_programs_detect_loops_on_the_whole_machine() {
+        let mut program = Program::<ExtendedOp>::parse_operations("jmp +0").unwrap();
+        assert_eq!(
+            program.run(None),
+            ExecutionOutcome::InfiniteLoop {
+                pc: 0,
+                acc: 0,
+                trace: vec![0, 0],
+            }
+        );
+        assert!(program.execute().is_err());
+
+        // the jz runs twice with the same acc, so it will keep falling through forever
+        let mut program =
+            Program::<ExtendedOp>::parse_operations("acc +1\njz +2\njmp -1\nout 0").unwrap();
+        assert!(matches!(
+            program.run(None),
+            ExecutionOutcome::InfiniteLoop { pc: 1, acc: 1, .. }
+        ));
+
+        // revisiting a line with more input consumed isn't a loop
+        let mut program = Program::<ExtendedOp>::parse_operations("in 0\njnz -1").unwrap();
+        program.ports.feed(0, vec![3, 2, 1, 0]);
+        assert!(matches!(
+            program.run(None),
+            ExecutionOutcome::Terminated { acc: 0, .. }
+        ));
+
+        // rewinding forgets the states that came after the snapshot
+        program.reset_program();
+        program.ports.feed(0, vec![3, 0]);
+        let snapshot = program.snapshot();
+        program.run(None);
+        program.restore(&snapshot).unwrap();
+        assert!(matches!(
+            program.run(None),
+            ExecutionOutcome::Terminated { .. }
+        ));
+    }
+
+    #[test]
+    fn extended_programs_report_faults() {
+        let mut program = Program::<ExtendedOp>::parse_operations("acc +1\nin 0\nout 0").unwrap();
+        let outcome = program.run(None);
+
+        assert_eq!(
+            outcome,
+            ExecutionOutcome::Fault {
+                pc: 1,
+                message: "Port 0 has no input".to_string(),
+                acc: 1,
+                trace: vec![0, 1],
+            }
+        );
+        assert_eq!(outcome.to_string(), "Line 2 failed: Port 0 has no input");
+
+        let mut program = Program::<ExtendedOp>::parse_operations("in 0\nout 0").unwrap();
+        for _ in 0..2 {
+            assert!(matches!(
+                program.run(None),
+                ExecutionOutcome::Fault { pc: 0, .. }
+            ));
+        }
+
+        let mut program = Program::<ExtendedOp>::parse_operations("acc +1\njz +2\nmul -3").unwrap();
+        assert!(matches!(
+            program.run(None),
+            ExecutionOutcome::Terminated { acc: -3, .. }
+        ));
+
+        let error = Program::<ExtendedOp>::parse_operations("acc +1\nsta r9")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(error, "line 2: Invalid register \"r9\"");
+    }
+
+    #[test]
+    fn traces_export_as_jsonl() {
+        let mut program = Program::parse_from_text(indoc! {"
+            nop +0
+            acc +1
+            jmp +4
+            acc +3
+            jmp -3
+            acc -99
+            acc +1
+            jmp -4
+            acc +6"});
+        assert_eq!(program.trace_jsonl(), "");
+
+        program.enable_tracing();
+        program.run(None);
+        let jsonl = program.trace_jsonl();
+        let lines: Vec<&str> = jsonl.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(
+            lines[0],
+            r#"{"step":0,"pc":0,"opcode":"nop","operand":0,"acc_before":0,"acc_after":0}"#
+        );
+        assert_eq!(
+            lines[6],
+            r#"{"step":6,"pc":4,"opcode":"jmp","operand":-3,"acc_before":5,"acc_after":5}"#
+        );
+
+        program.reset_program();
+        assert_eq!(program.trace_jsonl(), "");
+
+        let mut program = Program::<ExtendedOp>::parse_operations("sta r2\nout 1").unwrap();
+        program.enable_tracing();
+        program.run(None);
+        assert!(program
+            .trace_jsonl()
+            .starts_with(r#"{"step":0,"pc":0,"opcode":"sta","operand":"r2","#));
+
+        // events are numbered by the step they ran on, even when tracing starts late
+        let mut program = Program::parse_from_text("acc +1\nacc +2\nacc +3\nacc +4");
+        program.program_step().unwrap();
+        program.program_step().unwrap();
+        program.enable_tracing();
+        let snapshot = program.snapshot();
+        program.program_step().unwrap();
+        assert!(program.trace_jsonl().starts_with(r#"{"step":2,"pc":2,"#));
+        program.program_step().unwrap();
+        program.restore(&snapshot).unwrap();
+        assert_eq!(program.trace_jsonl(), "");
+    }
+
+    #[test]
+    fn profiles_count_runs_and_hot_loops() {
+        let mut program = Program::parse_from_text(indoc! {"
+            nop +0
+            acc +1
+            jmp +4
+            acc +3
+            jmp -3
+            acc -99
+            acc +1
+            jmp -4
+            acc +6"});
+        program.run(None);
+        let profile = program.profile();
+        assert_eq!(profile.counts, vec![1, 1, 1, 1, 1, 0, 1, 1, 0]);
+        assert_eq!(
+            profile.hot_loops,
+            vec![
+                HotLoop {
+                    start: 1,
+                    end: 4,
+                    iterations: 1
+                },
+                HotLoop {
+                    start: 3,
+                    end: 7,
+                    iterations: 1
+                }
+            ]
+        );
+        assert!(profile.to_string().ends_with(
+            "hot loops:\n  lines 2-5 jumped back 1 times\n  lines 4-8 jumped back 1 times\n"
+        ));
+
+        // counts 3 down to 0, the jnz runs three times and jumps back twice
+        let mut program =
+            Program::<ExtendedOp>::parse_operations("acc +3\nacc -1\njnz -1\nout 0").unwrap();
+        program.run(None);
+        let profile = program.profile();
+        assert_eq!(profile.counts, vec![1, 3, 3, 1]);
+        assert_eq!(
+            profile.hot_loops,
+            vec![HotLoop {
+                start: 1,
+                end: 2,
+                iterations: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn snapshots_restore_the_machine() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
+        program.enable_tracing();
+        program.program_step().unwrap();
+        program.program_step().unwrap();
+        let snapshot = program.snapshot();
+        let jsonl = program.trace_jsonl();
+
+        program.run(None);
+        assert_eq!(program.acc, 5);
+        program.restore(&snapshot).unwrap();
+        assert_eq!(program.snapshot(), snapshot);
+        assert_eq!((program.program_counter, program.acc), (2, 1));
+        assert_eq!(program.trace, vec![0, 1, 2]);
+        assert_eq!(program.trace_jsonl(), jsonl);
+        assert!(program.instructions[1].executed);
+        assert!(!program.instructions[2].executed);
+
+        // the rest of the run plays out the same way again
+        assert_eq!(
+            program.run(None),
+            ExecutionOutcome::InfiniteLoop {
+                pc: 1,
+                acc: 5,
+                trace: vec![0, 1, 2, 6, 7, 3, 4, 1]
+            }
+        );
+    }
+
+    #[test]
+    fn snapshots_from_elsewhere_are_rejected() {
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
+        program.program_step().unwrap();
+        let ahead = program.snapshot();
+
+        // a snapshot from the future of this run
+        program.reset_program();
+        let error = program.restore(&ahead).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Snapshot was taken at step 1 but the program is at step 0"
+        );
+        assert_eq!((program.program_counter, program.steps()), (0, 0));
+
+        // a snapshot of a run that went down a different path
+        let mut other = Program::parse_from_text("jmp +2\nnop +0\nnop +0");
+        other.program_step().unwrap();
+        program.program_step().unwrap();
+        program.program_step().unwrap();
+        let error = program.restore(&other.snapshot()).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Snapshot is from a different run, step 1 was on line 2"
+        );
+        assert_eq!((program.program_counter, program.acc), (2, 1));
+    }
+
+    #[test]
+    fn history_keeps_only_the_latest_snapshots() {
+        let mut program = Program::parse_from_text("acc +1\nacc +2\nacc +3");
+        let mut history = History::new(2);
+        while program.halt_reason().is_none() {
+            history.push(program.snapshot());
+            program.program_step().unwrap();
+        }
+
+        program.restore(&history.pop().unwrap()).unwrap();
+        assert_eq!(program.acc, 3);
+        program.restore(&history.pop().unwrap()).unwrap();
+        assert_eq!(program.acc, 1);
+        assert_eq!(history.pop(), None);
+    }
+
+    #[test]
+    fn debugger_can_step_backwards() {
+        let mut debugger = Debugger::new(Program::parse_from_text(include_str!(
+            "inputs/day_eight.test.txt"
+        )));
+        debugger.run_command("step 4").unwrap();
+        assert_eq!(debugger.run_command("print acc").unwrap(), "2");
+
+        assert_eq!(debugger.run_command("back").unwrap(), "=>    7  acc +1");
+        assert_eq!(debugger.run_command("print acc").unwrap(), "1");
+        debugger.run_command("back 3").unwrap();
+        assert_eq!(debugger.run_command("print pc").unwrap(), "0 (line 1)");
+        assert!(debugger.run_command("back").is_err());
+
+        // stepping again after going back retraces the same path
+        debugger.run_command("step 7").unwrap();
+        assert_eq!(debugger.program.acc, 5);
+        assert!(debugger.run_command("step").is_err());
+        debugger.run_command("reset").unwrap();
+        assert!(debugger.run_command("back").is_err());
+    }
+
+    #[test]
+    fn patches_run_without_touching_the_program() {
+        let source = include_str!("inputs/day_eight.txt");
+        let program = Program::parse_from_text(source);
+        let repair = program.find_repairs()[0];
+        let patch = program.repair_patch(&repair);
+        assert_eq!(patch.to_text(), "211: jmp -31 -> nop -31\n");
+
+        let patched = Patched::new(&program, &patch).unwrap();
+        assert_eq!(patched.operation(210), Op::Nop(-31));
+        assert!(matches!(
+            patched.run(None),
+            ExecutionOutcome::Terminated { acc: 2060, .. }
+        ));
+        assert_eq!(program.instructions[210].operation, Op::Jump(-31));
+        assert!(program
+            .instructions
+            .iter()
+            .all(|instruction| !instruction.executed));
+
+        let wrong = Patch::parse("211: nop -31 -> jmp -31").unwrap();
+        assert_eq!(
+            Patched::new(&program, &wrong).err().unwrap().to_string(),
+            "Line 211 is jmp -31 but the patch expects nop -31"
+        );
+        let past_the_end = Patch::parse("9999: acc +1 -> nop +1").unwrap();
+        assert!(Patched::new(&program, &past_the_end).is_err());
+    }
+
+    #[test]
+    fn patches_round_trip_and_reapply_to_source() {
+        let mut patch = Patch::new();
+        patch.substitute(7, Op::Jump(-4), Op::Nop(-4)).unwrap();
+        patch.substitute(0, Op::Nop(0), Op::Acc(10)).unwrap();
+        assert!(patch.substitute(7, Op::Jump(-4), Op::Acc(1)).is_err());
+        assert_eq!(Patch::parse(&patch.to_text()).unwrap(), patch);
+        assert!(Patch::<Op>::parse("8 jmp -4 -> nop -4").is_err());
+        assert!(Patch::<Op>::parse("0: jmp -4 -> nop -4").is_err());
+
+        let source = "nop +0\r\nacc +1\r\njmp +4\r\n\r\nacc +3\r\njmp -3\r\nacc -99\r\nacc +1\r\njmp -4\r\nacc +6\r\n";
+        let fixed = patch.apply_to_source(source).unwrap();
+        assert_eq!(fixed, "acc +10\r\nacc +1\r\njmp +4\r\n\r\nacc +3\r\njmp -3\r\nacc -99\r\nacc +1\r\nnop -4\r\nacc +6\r\n");
+        assert!(patch.apply_to_source("nop +0").is_err());
+
+        let mut program = Program::parse_from_text(include_str!("inputs/day_eight.test.txt"));
+        patch.apply(&mut program).unwrap();
+        assert!(program.execute().is_ok());
+        assert_eq!(program.acc, 18);
+        assert!(patch.apply(&mut program).is_err());
+    }
+
+    #[test]
+    fn generated_programs_respect_the_config() {
+        let config = GeneratorConfig {
+            size: 200,
+            mix: OpcodeMix {
+                acc: 1,
+                jmp: 0,
+                nop: 1,
+            },
+            max_acc: 3,
+        };
+        let program = ProgramGenerator::new(config, 7).unwrap().generate();
+        assert_eq!(program.instructions.len(), 200);
+        for (index, instruction) in program.instructions.iter().enumerate() {
+            match instruction.operation {
+                Op::Acc(amount) => assert!((-3..=3).contains(&amount)),
+                Op::Nop(offset) => {
+                    assert!((0..=200).contains(&(index as isize + offset)))
+                }
+                Op::Jump(_) => panic!("the mix has no jmps"),
+            }
+        }
+
+        let mut first = ProgramGenerator::new(GeneratorConfig::default(), 1).unwrap();
+        let mut second = ProgramGenerator::new(GeneratorConfig::default(), 1).unwrap();
+        assert_eq!(first.generate().to_text(), second.generate().to_text());
+    }
+
+    #[test]
+    fn generators_reject_configs_they_cant_use() {
+        let mut rng = Rng::new(0x9E37_79B9_7F4A_7C15);
+        assert_ne!((rng.next_u64(), rng.next_u64()), (0, 0));
+
+        let config = GeneratorConfig::default();
+        let with_mix = |acc, jmp, nop| GeneratorConfig {
+            mix: OpcodeMix { acc, jmp, nop },
+            ..config
+        };
+        assert!(ProgramGenerator::new(GeneratorConfig { size: 0, ..config }, 1).is_err());
+        assert!(ProgramGenerator::new(with_mix(0, 0, 0), 1).is_err());
+        assert!(ProgramGenerator::new(with_mix(u64::MAX, 1, 0), 1).is_err());
+        assert!(ProgramGenerator::new(
+            GeneratorConfig {
+                max_acc: -1,
+                ..config
+            },
+            1
+        )
+        .is_err());
+        assert!(ProgramGenerator::new(
+            GeneratorConfig {
+                max_acc: isize::MAX,
+                ..config
+            },
+            1
+        )
+        .is_err());
+
+        // only acc means nothing can be flipped
+        let mut generator = ProgramGenerator::new(with_mix(1, 0, 0), 1).unwrap();
+        assert!(generator.generate_corrupted(1000).is_none());
+    }
+
+    #[test]
+    fn self_debug_agrees_with_find_repairs_on_generated_programs() {
+        for seed in 0..50 {
+            let mut generator = ProgramGenerator::new(GeneratorConfig::default(), seed).unwrap();
+            let (program, repair) = generator.generate_corrupted(1000).unwrap();
+            assert_eq!(program.find_repairs(), vec![repair]);
+
+            let patch = program.repair_patch(&repair);
+            let outcome = Patched::new(&program, &patch).unwrap().run(None);
+
+            let mut fixed = program.self_debug();
+            assert_eq!(
+                fixed.instructions[repair.index].operation,
+                repair.replacement
+            );
+            assert!(fixed.execute().is_ok());
+            assert_eq!(outcome.acc(), fixed.acc, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn overflows_are_faults_not_panics() {
+        let mut program = Program::parse_from_text("acc +9223372036854775807\nacc +1");
+        assert_eq!(
+            program.run(None).to_string(),
+            "Line 2 failed: The accumulator overflowed"
+        );
+
+        let mut program = Program::parse_from_text("nop +0\njmp +9223372036854775807");
+        assert_eq!(
+            program.run(None).to_string(),
+            "Line 2 failed: Jumped past the end of the address space"
+        );
+
+        let mut program = Program::parse_from_text("jmp +9223372036854775807");
+        assert_eq!(
+            program.run(None).to_string(),
+            "Jumped out of the program to line 9223372036854775808"
+        );
+    }
+
+    #[test]
+    fn fuzzing_never_panics() {
+        let edge_cases = vec![
+            "",
+            "\n\n",
+            "jmp",
+            "jmp +9223372036854775807",
+            "jmp -9223372036854775808",
+            "nop +9223372036854775807\njmp -9223372036854775808",
+            "acc +9223372036854775807\nacc +1",
+            "acc -9223372036854775808\nacc -1",
+            "jmp +1\nlist -9223372036854775808\nlist 9223372036854775807\nstep 99999\nback 99999",
+            "jmp +9223372036854775807\nstep\nlist\nprint pc",
+            "in 0\nmul +9223372036854775807\nmul +9223372036854775807",
+            "1: jmp -4 -> nop -4\n1: nop +0 -> acc +1",
+            "99999999999999999999: jmp -4 -> nop -4",
+            "a:\njmp a\nb: c:\nnop b",
+            "\u{0}\u{ff}é ✓ jmp +١",
+        ];
+        for text in edge_cases {
+            fuzz_text(text);
+        }
+
+        // random lines built from pieces of valid and invalid syntax
+        let pieces = [
+            "jmp",
+            "nop",
+            "acc",
+            "mul",
+            "jz",
+            "jnz",
+            "lda",
+            "sta",
+            "add",
+            "in",
+            "out",
+            "+0",
+            "-1",
+            "+2",
+            "r0",
+            "r7",
+            "0",
+            "x:",
+            ":",
+            "->",
+            "#",
+            ";",
+            "1:",
+            "step",
+            "back",
+            "continue",
+            "list",
+            "break",
+            "9223372036854775807",
+            "-9223372036854775808",
+            "\t",
+            "\r",
+        ];
+        let mut rng = Rng::new(2020);
+        for _ in 0..500 {
+            let mut text = String::new();
+            for _ in 0..rng.below(12) {
+                for _ in 0..rng.below(4) {
+                    text += pieces[rng.below(pieces.len() as u64) as usize];
+                    text.push(' ');
+                }
+                text.push('\n');
+            }
+            fuzz_text(&text);
+        }
     }
 }
@@ -1,62 +1,301 @@
-
+use std::error::Error;
 use std::ops::Range;
 
 #[derive(Debug)]
 struct Finder(Range<u8>);
 
+/// Describes how boarding passes map onto the seats of a plane. A pass is `row_bits` row symbols
+/// followed by `column_bits` column symbols, each alphabet written as (zero, one).
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct AircraftLayout {
+    row_bits: usize,
+    column_bits: usize,
+    row_symbols: (char, char),
+    column_symbols: (char, char),
+    // a seat id is row * row_multiplier + column
+    row_multiplier: usize,
+}
+
+impl AircraftLayout {
+    /// The 128 row, 8 column plane from the puzzle
+    const STANDARD: AircraftLayout = AircraftLayout {
+        row_bits: 7,
+        column_bits: 3,
+        row_symbols: ('F', 'B'),
+        column_symbols: ('L', 'R'),
+        row_multiplier: 8,
+    };
+
+    /// A layout whose every seat has its own id that fits in a usize
+    fn new(
+        (row_bits, column_bits): (usize, usize),
+        row_symbols: (char, char),
+        column_symbols: (char, char),
+        row_multiplier: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let bits = usize::BITS as usize;
+        if row_bits >= bits || column_bits >= bits {
+            return Err(
+                format!("A plane can have at most {} row and column bits", bits - 1).into(),
+            );
+        }
+        if row_symbols.0 == row_symbols.1 || column_symbols.0 == column_symbols.1 {
+            return Err("Each half of a pass needs two different symbols".into());
+        }
+
+        let layout = Self {
+            row_bits,
+            column_bits,
+            row_symbols,
+            column_symbols,
+            row_multiplier,
+        };
+        if row_multiplier < layout.columns() {
+            return Err(format!(
+                "A row multiplier of {} would give seats in a row of {} the same id",
+                row_multiplier,
+                layout.columns()
+            )
+            .into());
+        }
+        (layout.rows() - 1)
+            .checked_mul(row_multiplier)
+            .and_then(|id| id.checked_add(layout.columns() - 1))
+            .ok_or("The highest seat id doesn't fit in a usize")?;
+
+        Ok(layout)
+    }
+
+    fn rows(&self) -> usize {
+        1 << self.row_bits
+    }
+
+    fn columns(&self) -> usize {
+        1 << self.column_bits
+    }
+
+    fn pass_length(&self) -> usize {
+        self.row_bits + self.column_bits
+    }
+
+    fn decode_row(&self, source: &str) -> Result<usize, Box<dyn Error>> {
+        decode_symbols(source, self.row_bits, self.row_symbols)
+    }
+
+    fn decode_column(&self, source: &str) -> Result<usize, Box<dyn Error>> {
+        decode_symbols(source, self.column_bits, self.column_symbols)
+    }
+
+    fn decode(&self, pass: &str) -> Result<(usize, usize), Box<dyn Error>> {
+        if pass.chars().count() != self.pass_length() {
+            return Err(format!(
+                "A boarding pass is exactly {} characters, got {:?}",
+                self.pass_length(),
+                pass
+            )
+            .into());
+        }
+
+        let split = pass
+            .char_indices()
+            .nth(self.row_bits)
+            .map_or(pass.len(), |(index, _)| index);
+        let (row, column) = pass.split_at(split);
+
+        Ok((self.decode_row(row)?, self.decode_column(column)?))
+    }
+
+    fn encode(&self, (row, col): (usize, usize)) -> Result<String, Box<dyn Error>> {
+        if row >= self.rows() || col >= self.columns() {
+            return Err(format!("Seat {:?} is not on the plane", (row, col)).into());
+        }
+
+        Ok(encode_symbols(row, self.row_bits, self.row_symbols)
+            + &encode_symbols(col, self.column_bits, self.column_symbols))
+    }
+
+    fn seat_id(&self, (row, col): (usize, usize)) -> usize {
+        row * self.row_multiplier + col
+    }
+
+    fn seat_from_id(&self, id: usize) -> Option<(usize, usize)> {
+        let seat = (id / self.row_multiplier, id % self.row_multiplier);
+        if seat.0 < self.rows() && seat.1 < self.columns() {
+            Some(seat)
+        } else {
+            None
+        }
+    }
+
+    // a human readable line for a seat, eg: BFFFBBFRRR: row 70, column 7, seat ID 567
+    fn describe(&self, id: usize) -> Result<String, Box<dyn Error>> {
+        let (row, col) = self
+            .seat_from_id(id)
+            .ok_or_else(|| format!("Seat id {} is not on the plane", id))?;
+
+        Ok(format!(
+            "{}: row {}, column {}, seat ID {}",
+            self.encode((row, col))?,
+            row,
+            col,
+            id
+        ))
+    }
+}
+
+// reads a run of binary symbols most significant first, eg: FBF with ('F', 'B') is 2
+fn decode_symbols(
+    source: &str,
+    length: usize,
+    (zero, one): (char, char),
+) -> Result<usize, Box<dyn Error>> {
+    if source.chars().count() != length {
+        return Err(format!("Exactly {} symbols are required, got {:?}", length, source).into());
+    }
+
+    source.chars().try_fold(0, |value, symbol| match symbol {
+        _ if symbol == zero => Ok(value << 1),
+        _ if symbol == one => Ok(value << 1 | 1),
+        _ => Err(format!("Invalid symbol {:?} in {:?}", symbol, source).into()),
+    })
+}
+
+fn encode_symbols(value: usize, length: usize, (zero, one): (char, char)) -> String {
+    (0..length)
+        .rev()
+        .map(|bit| if value >> bit & 1 == 1 { one } else { zero })
+        .collect()
+}
+
 fn find_seat_row_number(source: &str) -> u8 {
-    if source.len() != 7 {
-        panic!("Exactly 7 sympols are required to find a seat row")
-    }
-
-    u8::from_str_radix(
-        &source
-            .chars()
-            .map(|char| match char {
-                'F' => '0',
-                'B' => '1',
-                _ => panic!("Invalid Command"),
-            })
-            .collect::<String>(),
-        2,
-    )
-    .unwrap()
+    AircraftLayout::STANDARD.decode_row(source).unwrap() as u8
 }
 
 fn find_column_number(source: &str) -> u8 {
-    if source.len() != 3 {
-        panic!("Exactly 3 sympols are required to find a seat row")
-    }
-
-    u8::from_str_radix(
-        &source
-            .chars()
-            .map(|char| match char {
-                'L' => '0',
-                'R' => '1',
-                _ => panic!("Invalid Command"),
-            })
-            .collect::<String>(),
-        2,
-    )
-    .unwrap()
+    AircraftLayout::STANDARD.decode_column(source).unwrap() as u8
 }
 
 fn process_boarding_pass(pass: &str) -> (u8, u8) {
-    if pass.len() != 10 {
-        panic!("A boarding pass is exactly 10 characters");
+    let (row, col) = AircraftLayout::STANDARD.decode(pass).unwrap();
+
+    (row as u8, col as u8)
+}
+
+// the inverse of process_boarding_pass, (70, 7) becomes BFFFBBFRRR
+fn encode_boarding_pass(row: u8, col: u8) -> String {
+    AircraftLayout::STANDARD
+        .encode((row as usize, col as usize))
+        .unwrap()
+}
+
+fn seat_id((row, col): (u8, u8)) -> usize {
+    AircraftLayout::STANDARD.seat_id((row as usize, col as usize))
+}
+
+fn seat_from_id(id: usize) -> (u8, u8) {
+    let (row, col) = AircraftLayout::STANDARD
+        .seat_from_id(id)
+        .expect("Seat id is not on the plane");
+
+    (row as u8, col as u8)
+}
+
+fn describe_boarding_pass(id: usize) -> String {
+    AircraftLayout::STANDARD.describe(id).unwrap()
+}
+
+/// Every boarding pass laid out over the cabin. Passes that can't be decoded are kept aside with
+/// the reason rather than panicking so a whole batch can be checked at once.
+#[derive(Debug)]
+struct SeatMap<'a> {
+    layout: AircraftLayout,
+    // number of passes issued for each seat, indexed by row * columns + column
+    occupancy: Vec<usize>,
+    duplicate_passes: Vec<&'a str>,
+    rejected_passes: Vec<(&'a str, String)>,
+}
+
+impl<'a> SeatMap<'a> {
+    fn from_passes(layout: AircraftLayout, passes: impl Iterator<Item = &'a str>) -> Self {
+        let mut map = Self {
+            layout,
+            occupancy: vec![0; layout.rows() * layout.columns()],
+            duplicate_passes: vec![],
+            rejected_passes: vec![],
+        };
+
+        for pass in passes {
+            match layout.decode(pass) {
+                Ok((row, col)) => {
+                    let count = &mut map.occupancy[row * layout.columns() + col];
+                    if *count > 0 {
+                        map.duplicate_passes.push(pass);
+                    }
+                    *count += 1;
+                }
+                Err(error) => map.rejected_passes.push((pass, error.to_string())),
+            }
+        }
+
+        map
+    }
+
+    fn seats(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.layout.rows())
+            .flat_map(move |row| (0..self.layout.columns()).map(move |col| (row, col)))
+    }
+
+    fn is_occupied(&self, (row, col): (usize, usize)) -> bool {
+        self.occupancy[row * self.layout.columns() + col] > 0
     }
 
-    (
-        find_seat_row_number(&pass[0..7]),
-        find_column_number(&pass[7..10]),
-    )
+    fn is_id_occupied(&self, id: usize) -> bool {
+        self.layout
+            .seat_from_id(id)
+            .is_some_and(|seat| self.is_occupied(seat))
+    }
+
+    /// Seat ids with no boarding pass, in ascending order
+    fn empty_seats(&self) -> Vec<usize> {
+        self.seats()
+            .filter(|&seat| !self.is_occupied(seat))
+            .map(|seat| self.layout.seat_id(seat))
+            .collect()
+    }
+
+    /// Empty seats where both the seat ids either side have a pass. This is how part b finds ours.
+    fn gaps(&self) -> Vec<usize> {
+        self.empty_seats()
+            .into_iter()
+            .filter(|&id| id > 0 && self.is_id_occupied(id - 1) && self.is_id_occupied(id + 1))
+            .collect()
+    }
+
+    // # for a taken seat, . for an empty one and ! where more than one pass was issued
+    fn render(&self) -> String {
+        let mut output = String::new();
+        for row in 0..self.layout.rows() {
+            output += &format!("{:>4} ", row);
+            for col in 0..self.layout.columns() {
+                output.push(match self.occupancy[row * self.layout.columns() + col] {
+                    0 => '.',
+                    1 => '#',
+                    _ => '!',
+                });
+            }
+            output.push('\n');
+        }
+
+        output
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::day_five::{find_column_number, find_seat_row_number, process_boarding_pass};
-    
+    use crate::day_five::{
+        describe_boarding_pass, encode_boarding_pass, find_column_number, find_seat_row_number,
+        process_boarding_pass, seat_from_id, seat_id, AircraftLayout, SeatMap,
+    };
 
     #[test]
     fn can_find_a_row() {
@@ -84,7 +323,7 @@ mod tests {
 
         for boarding_pass in boarding_passes {
             let (row, col) = process_boarding_pass(boarding_pass);
-            max = max.max((row as usize * 8usize) + col as usize)
+            max = max.max(seat_id((row, col)))
         }
 
         assert_eq!(max, 911)
@@ -92,20 +331,132 @@ mod tests {
 
     #[test]
     fn production_run_b() {
-        let all_passes = include_str!("inputs/day_five.txt")
-            .lines()
-            .map(process_boarding_pass)
-            .map(|(row, col)| (row as usize * 8) + col as usize)
-            .collect::<Vec<usize>>();
+        let seat_map = SeatMap::from_passes(
+            AircraftLayout::STANDARD,
+            include_str!("inputs/day_five.txt").lines(),
+        );
+
+        assert_eq!(seat_map.gaps(), vec![629]);
+        assert!(seat_map.duplicate_passes.is_empty());
+        assert!(seat_map.rejected_passes.is_empty());
+        assert_eq!(
+            describe_boarding_pass(629),
+            "BFFBBBFRLR: row 78, column 5, seat ID 629"
+        );
+    }
 
-        // get our id by getting the theoretical maximum and subtracting the actual sum of all passes
-        let maximum = all_passes.iter().max().unwrap();
-        let minimum = all_passes.iter().min().unwrap();
-        let sum_of_all_passes: usize = all_passes.iter().sum();
+    #[test]
+    fn seat_map_reports_gaps_duplicates_and_strays() {
+        let layout = AircraftLayout::new((2, 2), ('F', 'B'), ('L', 'R'), 4).unwrap();
+        let passes = vec![
+            "FFLL", "FFLR", "FFRR", "FBLL", "FBLL", "FBRL", "BBRR", "FFFLL", "FXLL",
+        ];
+        let seat_map = SeatMap::from_passes(layout, passes.into_iter());
 
-        let theoretical_maximum_sum_of_all_seats =
-            (maximum.pow(2) - minimum.pow(2) - maximum - minimum) / 2;
+        assert_eq!(
+            seat_map.empty_seats(),
+            vec![2, 5, 7, 8, 9, 10, 11, 12, 13, 14]
+        );
+        assert_eq!(seat_map.gaps(), vec![2, 5]);
+        assert_eq!(seat_map.duplicate_passes, vec!["FBLL"]);
+        assert_eq!(
+            seat_map.rejected_passes,
+            vec![
+                (
+                    "FFFLL",
+                    "A boarding pass is exactly 4 characters, got \"FFFLL\"".to_string()
+                ),
+                ("FXLL", "Invalid symbol 'X' in \"FX\"".to_string())
+            ]
+        );
+        assert_eq!(
+            seat_map.render(),
+            "   0 ##.#\n   1 !.#.\n   2 ....\n   3 ...#\n"
+        );
+    }
 
-        assert_eq!(theoretical_maximum_sum_of_all_seats - sum_of_all_passes, 9)
+    #[test]
+    fn it_can_encode_a_boarding_pass() {
+        assert_eq!(encode_boarding_pass(70, 7), "BFFFBBFRRR");
+        assert_eq!(encode_boarding_pass(14, 7), "FFFBBBFRRR");
+        assert_eq!(encode_boarding_pass(102, 4), "BBFFBBFRLL");
+        assert_eq!(encode_boarding_pass(0, 0), "FFFFFFFLLL");
+    }
+
+    #[test]
+    fn every_seat_round_trips() {
+        for id in 0..=seat_id((127, 7)) {
+            let (row, col) = seat_from_id(id);
+            assert_eq!(seat_id((row, col)), id);
+            assert_eq!(
+                process_boarding_pass(&encode_boarding_pass(row, col)),
+                (row, col)
+            );
+        }
+    }
+
+    #[test]
+    fn production_passes_round_trip() {
+        for boarding_pass in include_str!("inputs/day_five.txt").lines() {
+            let (row, col) = process_boarding_pass(boarding_pass);
+            assert_eq!(encode_boarding_pass(row, col), boarding_pass);
+        }
+    }
+
+    #[test]
+    fn it_can_describe_a_boarding_pass() {
+        assert_eq!(
+            describe_boarding_pass(567),
+            "BFFFBBFRRR: row 70, column 7, seat ID 567"
+        );
+    }
+
+    #[test]
+    fn it_rejects_malformed_passes() {
+        let layout = AircraftLayout::STANDARD;
+
+        assert!(layout.decode("BFFFBBFRR").is_err());
+        assert!(layout.decode("BFFFBBFRRRR").is_err());
+        assert!(layout.decode("BFFFBBFRRX").is_err());
+        assert!(layout.decode("RFFFBBFRRR").is_err());
+        assert!(layout.encode((128, 0)).is_err());
+        assert_eq!(layout.seat_from_id(1024), None);
+    }
+
+    #[test]
+    fn layouts_can_describe_other_planes() {
+        // a small regional plane with 16 rows of 4 seats, ids are the row then the column digit
+        let layout = AircraftLayout::new((4, 2), ('0', '1'), ('A', 'B'), 10).unwrap();
+
+        assert_eq!(layout.decode("0110BA").unwrap(), (6, 2));
+        assert_eq!(layout.encode((6, 2)).unwrap(), "0110BA");
+        assert_eq!(layout.seat_id((6, 2)), 62);
+        assert_eq!(layout.seat_from_id(62), Some((6, 2)));
+        assert_eq!(layout.seat_from_id(65), None);
+        assert_eq!(
+            layout.describe(153).unwrap(),
+            "1111BB: row 15, column 3, seat ID 153"
+        );
+
+        for row in 0..layout.rows() {
+            for col in 0..layout.columns() {
+                let pass = layout.encode((row, col)).unwrap();
+                assert_eq!(layout.decode(&pass).unwrap(), (row, col));
+            }
+        }
+    }
+
+    #[test]
+    fn layouts_are_checked_when_built() {
+        assert_eq!(
+            AircraftLayout::new((7, 3), ('F', 'B'), ('L', 'R'), 8).unwrap(),
+            AircraftLayout::STANDARD
+        );
+        assert!(AircraftLayout::new((7, 3), ('F', 'B'), ('L', 'R'), 0).is_err());
+        assert!(AircraftLayout::new((7, 3), ('F', 'B'), ('L', 'R'), 7).is_err());
+        assert!(AircraftLayout::new((64, 3), ('F', 'B'), ('L', 'R'), 8).is_err());
+        assert!(AircraftLayout::new((7, 64), ('F', 'B'), ('L', 'R'), 1 << 63).is_err());
+        assert!(AircraftLayout::new((60, 3), ('F', 'B'), ('L', 'R'), 1 << 10).is_err());
+        assert!(AircraftLayout::new((7, 3), ('F', 'F'), ('L', 'R'), 8).is_err());
     }
 }
@@ -1,17 +1,150 @@
 use crate::day_four::Field::{
     BirthYear, CountryID, ExpirationYear, EyeColor, HairColor, Height, IssueYear,
 };
+use crate::utils::Records;
 use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::RangeInclusive;
+
+/// Units a height can be written in. Every unit maps onto a whole number of micrometres so
+/// conversions between them stay exact.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum LengthUnit {
+    Millimetre,
+    Centimetre,
+    Inch,
+    Foot,
+}
 
+impl LengthUnit {
+    fn micrometres(self) -> u64 {
+        match self {
+            LengthUnit::Millimetre => 1_000,
+            LengthUnit::Centimetre => 10_000,
+            LengthUnit::Inch => 25_400,
+            LengthUnit::Foot => 304_800,
+        }
+    }
 
-use std::hash::{Hash, Hasher};
+    fn from_suffix(suffix: &str) -> Option<Self> {
+        match suffix {
+            "mm" => Some(LengthUnit::Millimetre),
+            "cm" => Some(LengthUnit::Centimetre),
+            "in" | "\"" => Some(LengthUnit::Inch),
+            "ft" | "'" => Some(LengthUnit::Foot),
+            _ => None,
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            LengthUnit::Millimetre => "mm",
+            LengthUnit::Centimetre => "cm",
+            LengthUnit::Inch => "in",
+            LengthUnit::Foot => "ft",
+        }
+    }
+}
+
+/// A parsed measurement. The canonical value is kept in micrometres and the unit it was written
+/// in is remembered so we can print it back the way we found it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Length {
+    micrometres: u64,
+    unit: LengthUnit,
+}
+
+impl Length {
+    // None when the length is too long to hold in micrometres
+    fn new(amount: u64, unit: LengthUnit) -> Option<Self> {
+        Some(Self {
+            micrometres: amount.checked_mul(unit.micrometres())?,
+            unit,
+        })
+    }
+
+    // accepts 183cm, 1830mm, 72in, 6ft and the feet and inches form 5'11"
+    fn parse(source: &str) -> Option<Self> {
+        let source = source.trim();
+        if let Some(feet_end) = source.find('\'') {
+            let feet: u64 = source[..feet_end].parse().ok()?;
+            let inches = match &source[feet_end + 1..] {
+                "" => 0,
+                rest => rest.strip_suffix('"')?.parse().ok()?,
+            };
+            let feet = Self::new(feet, LengthUnit::Foot)?;
+            let inches = Self::new(inches, LengthUnit::Inch)?;
+            return Some(Self {
+                micrometres: feet.micrometres.checked_add(inches.micrometres)?,
+                unit: LengthUnit::Foot,
+            });
+        }
+
+        let split = source.find(|c: char| !c.is_ascii_digit())?;
+        let (amount, suffix) = source.split_at(split);
+        Self::new(amount.parse().ok()?, LengthUnit::from_suffix(suffix)?)
+    }
+
+    /// The length expressed in `unit`, possibly fractional
+    fn in_unit(&self, unit: LengthUnit) -> f64 {
+        self.micrometres as f64 / unit.micrometres() as f64
+    }
+
+    fn convert_to(&self, unit: LengthUnit) -> Self {
+        Self {
+            micrometres: self.micrometres,
+            unit,
+        }
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.unit {
+            LengthUnit::Foot => {
+                let inches = self.micrometres / LengthUnit::Inch.micrometres();
+                write!(f, "{}'{}\"", inches / 12, inches % 12)
+            }
+            unit => write!(f, "{}{}", self.in_unit(unit), unit.suffix()),
+        }
+    }
+}
+
+/// An inclusive range of lengths. The bounds are written in one unit but any length can be
+/// checked against it.
+#[derive(Debug, Clone)]
+struct LengthRange(RangeInclusive<u64>);
+
+impl LengthRange {
+    fn new(min: u64, max: u64, unit: LengthUnit) -> Self {
+        let micrometres = |amount| {
+            Length::new(amount, unit)
+                .expect("range bounds fit in micrometres")
+                .micrometres
+        };
+        Self(micrometres(min)..=micrometres(max))
+    }
+
+    fn contains(&self, length: &Length) -> bool {
+        self.0.contains(&length.micrometres)
+    }
+}
+
+// a height is valid if it falls within any of these ranges, regardless of the unit it's written in
+fn height_policy() -> [LengthRange; 2] {
+    [
+        LengthRange::new(150, 193, LengthUnit::Centimetre),
+        LengthRange::new(59, 76, LengthUnit::Inch),
+    ]
+}
 
 #[derive(Debug, Eq)]
 enum Field<'a> {
     BirthYear(usize),
     IssueYear(usize),
     ExpirationYear(usize),
-    Height(&'a str),
+    Height(Option<Length>),
     HairColor(&'a str),
     EyeColor(&'a str),
     PID(&'a str),
@@ -39,7 +172,7 @@ impl<'a> Field<'a> {
             "byr" => Some(BirthYear(value.parse().ok()?)),
             "iyr" => Some(IssueYear(value.parse().ok()?)),
             "eyr" => Some(ExpirationYear(value.parse().ok()?)),
-            "hgt" => Some(Height(value)),
+            "hgt" => Some(Height(Length::parse(value))),
             "hcl" => Some(HairColor(value)),
             "ecl" => Some(EyeColor(value)),
             "pid" => Some(Field::PID(value)),
@@ -66,10 +199,7 @@ impl<'a> Field<'a> {
             BirthYear(1920..=2002) => true,
             IssueYear(2010..=2020) => true,
             ExpirationYear(2020..=2030) => true,
-            Height(h) => match (&h[..h.len() - 2].parse::<usize>(), &h[h.len() - 2..]) {
-                (Ok(value @ 150..=193), unit @ "cm") | (Ok(value @ 59..=76), unit @ "in") => true,
-                _ => false,
-            },
+            Height(Some(ref height)) => height_policy().iter().any(|range| range.contains(height)),
             HairColor(color) => match (&color[0..1], &color[1..]) {
                 ("#", hex) => u32::from_str_radix(hex, 16).is_ok(),
                 _ => false,
@@ -119,30 +249,22 @@ impl<'a> Passport<'a> {
 }
 
 fn parse_passport_batch(source: &'static str) -> Vec<Passport> {
-    let lines: Vec<&str> = source.lines().collect();
-    let mut passports = vec![Default::default()];
-    for line in lines {
-        if line.is_empty() {
-            passports.push(Passport::default());
-            continue;
-        }
-
-        let current = passports.last_mut().unwrap();
-        let fields = line.split_whitespace();
-
-        for field in fields {
-            let field = Field::from_string_field(field).expect("Unhandled field type");
-            current.fields.insert(field);
-        }
-    }
-
-    passports
+    Records::new(source)
+        .map(|record| {
+            let mut passport = Passport::default();
+            for field in record.lines.iter().flat_map(|line| line.split_whitespace()) {
+                let field = Field::from_string_field(field).expect("Unhandled field type");
+                passport.fields.insert(field);
+            }
+            passport
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::day_four::Field::{BirthYear, EyeColor, IssueYear};
-    use crate::day_four::{parse_passport_batch, Field};
+    use crate::day_four::Field::{BirthYear, EyeColor, Height, IssueYear};
+    use crate::day_four::{parse_passport_batch, Field, Length, LengthRange, LengthUnit};
     use std::collections::HashSet;
 
     #[test]
@@ -152,6 +274,85 @@ mod tests {
         assert_eq!(Some(EyeColor("gray")), Field::from_string_field("ecl:gray"))
     }
 
+    #[test]
+    fn heights_are_parsed_into_lengths() {
+        assert_eq!(
+            Field::from_string_field("hgt:183cm"),
+            Some(Height(Some(
+                Length::new(183, LengthUnit::Centimetre).unwrap()
+            )))
+        );
+        assert_eq!(
+            Length::parse("5'11\""),
+            Some(
+                Length::new(71, LengthUnit::Inch)
+                    .unwrap()
+                    .convert_to(LengthUnit::Foot)
+            )
+        );
+        assert_eq!(
+            Length::parse("6'"),
+            Some(Length::new(6, LengthUnit::Foot).unwrap())
+        );
+        assert_eq!(
+            Length::parse("1830mm"),
+            Some(
+                Length::new(183, LengthUnit::Centimetre)
+                    .unwrap()
+                    .convert_to(LengthUnit::Millimetre)
+            )
+        );
+        assert_eq!(Length::parse("183"), None);
+        assert_eq!(Length::parse("5'11"), None);
+        assert_eq!(Length::parse("cm"), None);
+        assert_eq!(Length::parse("10000000000000000cm"), None);
+        assert_eq!(Length::parse("70000000000000'11\""), None);
+        assert_eq!(
+            Field::from_string_field("hgt:10000000000000000cm"),
+            Some(Height(None))
+        );
+    }
+
+    #[test]
+    fn lengths_convert_between_units() {
+        let height = Length::new(72, LengthUnit::Inch).unwrap();
+
+        assert_eq!(height.in_unit(LengthUnit::Centimetre), 182.88);
+        assert_eq!(height.in_unit(LengthUnit::Foot), 6.0);
+        assert_eq!(height.convert_to(LengthUnit::Foot).to_string(), "6'0\"");
+        assert_eq!(
+            height.convert_to(LengthUnit::Millimetre).to_string(),
+            "1828.8mm"
+        );
+        assert_eq!(
+            Length::new(71, LengthUnit::Inch)
+                .unwrap()
+                .convert_to(LengthUnit::Foot)
+                .to_string(),
+            "5'11\""
+        );
+    }
+
+    #[test]
+    fn length_ranges_accept_any_unit() {
+        let range = LengthRange::new(150, 193, LengthUnit::Centimetre);
+
+        assert!(range.contains(&Length::new(60, LengthUnit::Inch).unwrap()));
+        assert!(range.contains(&Length::parse("6'2\"").unwrap()));
+        assert!(range.contains(&Length::new(1930, LengthUnit::Millimetre).unwrap()));
+        assert!(!range.contains(&Length::new(59, LengthUnit::Inch).unwrap()));
+        assert!(!range.contains(&Length::new(1931, LengthUnit::Millimetre).unwrap()));
+    }
+
+    #[test]
+    fn height_validation_uses_typed_lengths() {
+        assert!(Field::from_string_field("hgt:60in").unwrap().validate());
+        assert!(Field::from_string_field("hgt:190cm").unwrap().validate());
+        assert!(Field::from_string_field("hgt:5'10\"").unwrap().validate());
+        assert!(!Field::from_string_field("hgt:190in").unwrap().validate());
+        assert!(!Field::from_string_field("hgt:190").unwrap().validate());
+    }
+
     #[test]
     fn field_hashes_ignore_values() {
         let mut set = HashSet::new();
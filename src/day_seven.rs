@@ -1,6 +1,8 @@
-use crate::utils::{split_into_array_by, split_once_at};
-
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
 
 #[derive(Debug, PartialOrd, PartialEq)]
 struct InputLine {
@@ -8,113 +10,420 @@ struct InputLine {
     containing_children: Vec<InputChild>,
 }
 
-impl InputLine {
-    // basic queries about input line internals
-    fn contains_bag_type(&self, bag_type: &'static str) -> bool {
-        self.containing_children
-            .iter()
-            .find(|c| c.name == bag_type)
-            .is_some()
-    }
-}
-
 #[derive(Debug, PartialOrd, PartialEq)]
 struct InputChild {
     count: usize,
     name: &'static str,
 }
 
-// light red bags contain 1 bright white bag, 2 muted yellow bag.
-fn parse_input_line(input_line: &'static str) -> InputLine {
-    let (name, rest) = split_once_at(input_line, "bags contain");
-    let (rest, _) = split_once_at(rest, ".");
-    let rules = if rest.contains("no other bags") {
-        vec![]
-    } else {
-        split_into_array_by(rest, ", ")
-            .iter()
-            .map(|&rule| {
-                let (count, name) = split_once_at(rule.trim(), " ");
-                let (name, _) = split_once_at(name, " bag");
-                InputChild {
-                    count: count.parse().expect("Unable to parse count"),
-                    name,
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Token {
+    Word(&'static str),
+    Number(&'static str),
+    Comma,
+    Period,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) | Token::Number(word) => write!(f, "{:?}", word),
+            Token::Comma => write!(f, "\",\""),
+            Token::Period => write!(f, "\".\""),
+        }
+    }
+}
+
+/// Where and why a rule failed to parse, `column` is one based
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    column: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+// splits a rule into words, numbers and punctuation, each paired with the byte offset it starts at
+fn tokenize(source: &'static str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((start, char)) = chars.next() {
+        let token = match char {
+            _ if char.is_whitespace() => continue,
+            ',' => Token::Comma,
+            '.' => Token::Period,
+            _ if char.is_ascii_alphanumeric() => {
+                let mut end = start + 1;
+                while let Some(&(index, next)) = chars.peek() {
+                    if !next.is_ascii_alphanumeric() {
+                        break;
+                    }
+                    end = index + 1;
+                    chars.next();
                 }
-            })
-            .collect()
-    };
+                let text = &source[start..end];
+                if text.chars().all(|c| c.is_ascii_digit()) {
+                    Token::Number(text)
+                } else {
+                    Token::Word(text)
+                }
+            }
+            _ => {
+                return Err(ParseError {
+                    column: start + 1,
+                    message: format!("unexpected character {:?}", char),
+                })
+            }
+        };
+        tokens.push((start, token));
+    }
+
+    Ok(tokens)
+}
+
+struct RuleParser {
+    source: &'static str,
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+}
+
+impl RuleParser {
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.position).map(|&(_, token)| token)
+    }
+
+    fn error(&self, expected: &str) -> ParseError {
+        match self.tokens.get(self.position) {
+            Some((offset, token)) => ParseError {
+                column: offset + 1,
+                message: format!("expected {} but found {}", expected, token),
+            },
+            None => ParseError {
+                column: self.source.len() + 1,
+                message: format!("expected {} but the rule ended", expected),
+            },
+        }
+    }
+
+    fn expect_word(&mut self, words: &[&str], expected: &str) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(Token::Word(word)) if words.contains(&word) => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(self.error(expected)),
+        }
+    }
+
+    // a bag name is every word up to "bag" or "bags", which is consumed along with it
+    fn bag_name(&mut self) -> Result<&'static str, ParseError> {
+        let start = self.position;
+        while let Some(Token::Word(word)) = self.peek() {
+            if word == "bag" || word == "bags" {
+                if self.position == start {
+                    return Err(self.error("a bag name"));
+                }
+                let first = self.tokens[start].0;
+                let (last, last_token) = self.tokens[self.position - 1];
+                let last_length = match last_token {
+                    Token::Word(text) | Token::Number(text) => text.len(),
+                    _ => 1,
+                };
+                self.position += 1;
+                return Ok(&self.source[first..last + last_length]);
+            }
+            self.position += 1;
+        }
+
+        if self.position == start {
+            Err(self.error("a bag name"))
+        } else {
+            Err(self.error("\"bag\" or \"bags\""))
+        }
+    }
+
+    fn child(&mut self) -> Result<InputChild, ParseError> {
+        let count = match self.peek() {
+            Some(Token::Number(count)) => count.parse().map_err(|_| ParseError {
+                column: self.tokens[self.position].0 + 1,
+                message: format!("{} is too many bags", count),
+            })?,
+            _ => return Err(self.error("a count")),
+        };
+        self.position += 1;
+
+        Ok(InputChild {
+            count,
+            name: self.bag_name()?,
+        })
+    }
+
+    fn rule(&mut self) -> Result<InputLine, ParseError> {
+        let target_bag = self.bag_name()?;
+        self.expect_word(&["contain", "contains"], "\"contain\"")?;
 
-    InputLine {
-        containing_children: rules,
-        target_bag: name.trim(),
+        let mut containing_children = vec![];
+        if self.peek() == Some(Token::Word("no")) {
+            self.position += 1;
+            self.expect_word(&["other"], "\"other\"")?;
+            self.expect_word(&["bag", "bags"], "\"bags\"")?;
+        } else {
+            containing_children.push(self.child()?);
+            while self.peek() == Some(Token::Comma) {
+                self.position += 1;
+                containing_children.push(self.child()?);
+            }
+        }
+
+        if self.peek() == Some(Token::Period) {
+            self.position += 1;
+        }
+        if self.peek().is_some() {
+            return Err(self.error("the end of the rule"));
+        }
+
+        Ok(InputLine {
+            target_bag,
+            containing_children,
+        })
+    }
+}
+
+// light red bags contain 1 bright white bag, 2 muted yellow bags.
+// either bag or bags is accepted after any name and the trailing period is optional
+fn try_parse_input_line(input_line: &'static str) -> Result<InputLine, ParseError> {
+    RuleParser {
+        source: input_line,
+        tokens: tokenize(input_line)?,
+        position: 0,
     }
+    .rule()
 }
 
+fn parse_input_line(input_line: &'static str) -> InputLine {
+    try_parse_input_line(input_line)
+        .unwrap_or_else(|error| panic!("Unable to parse {:?}: {}", input_line, error))
+}
+
+// prints the rule back in the puzzle's own format
+impl fmt::Display for InputLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bags contain ", self.target_bag)?;
+        if self.containing_children.is_empty() {
+            return write!(f, "no other bags.");
+        }
+
+        for (index, child) in self.containing_children.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            let noun = if child.count == 1 { "bag" } else { "bags" };
+            write!(f, "{} {} {}", child.count, child.name, noun)?;
+        }
+        write!(f, ".")
+    }
+}
+
+/// One edge of the bag graph, `count` of `bag` on the other end
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Edge {
+    bag: &'static str,
+    count: usize,
+}
+
+type Adjacency = HashMap<&'static str, Vec<Edge>>;
+
 #[derive(Debug)]
 struct RuleSearch {
     rule_map: HashMap<&'static str, InputLine>,
+    // bags that had more than one rule, only the last rule for each is kept
+    duplicate_rules: Vec<&'static str>,
+    // bag -> the bags it directly holds
+    contains: Adjacency,
+    // bag -> the bags that directly hold it
+    contained_by: Adjacency,
 }
 
 impl RuleSearch {
     fn from_input_lines(input_lines: impl Iterator<Item = InputLine>) -> Self {
         let mut map = HashMap::new();
+        let mut duplicate_rules = vec![];
 
         for line in input_lines {
-            map.insert(line.target_bag, line);
+            if let Some(previous) = map.insert(line.target_bag, line) {
+                duplicate_rules.push(previous.target_bag);
+            }
+        }
+
+        let mut contains = Adjacency::new();
+        let mut contained_by = Adjacency::new();
+        for line in map.values() {
+            for child in line.containing_children.iter() {
+                contains.entry(line.target_bag).or_default().push(Edge {
+                    bag: child.name,
+                    count: child.count,
+                });
+                contained_by.entry(child.name).or_default().push(Edge {
+                    bag: line.target_bag,
+                    count: child.count,
+                });
+            }
         }
 
-        Self { rule_map: map }
+        Self {
+            rule_map: map,
+            duplicate_rules,
+            contains,
+            contained_by,
+        }
     }
-    fn search_for_all_bags_that_contain_bag(
-        &self,
-        bag_type: &'static str,
-    ) -> HashSet<&'static str> {
-        let mut set = HashSet::new();
-        for (bag_name, line) in self.rule_map.iter() {
-            if line.contains_bag_type(&bag_type) {
-                set.insert(&**bag_name);
+
+    fn edges<'a>(adjacency: &'a Adjacency, bag_type: &str) -> &'a [Edge] {
+        adjacency
+            .get(bag_type)
+            .map_or(&[], |edges| edges.as_slice())
+    }
+
+    // breadth first walk along one direction of the graph. The starting bag is left out even when
+    // a cycle leads back to it
+    fn reachable_from(adjacency: &Adjacency, bag_type: &str) -> HashSet<&'static str> {
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::new();
+        queue.extend(Self::edges(adjacency, bag_type).iter().map(|edge| edge.bag));
+
+        while let Some(bag) = queue.pop_front() {
+            if seen.insert(bag) {
+                queue.extend(Self::edges(adjacency, bag).iter().map(|edge| edge.bag));
             }
         }
+        seen.remove(bag_type);
 
-        set
+        seen
+    }
+
+    fn search_for_all_bags_that_contain_bag(&self, bag_type: &str) -> HashSet<&'static str> {
+        Self::edges(&self.contained_by, bag_type)
+            .iter()
+            .map(|edge| edge.bag)
+            .collect()
     }
 
     fn search_for_all_bags_that_can_eventually_contain(
         &self,
-        bag_type: &'static str,
+        bag_type: &str,
     ) -> HashSet<&'static str> {
-        let mut output_set = self.search_for_all_bags_that_contain_bag(&bag_type);
-        // println!("{:?} direclty contains {}", &output_set, &bag_type);
-        let mut holding_set = output_set.clone();
-        loop {
-            let mut interim_set = HashSet::new();
-            for item in holding_set.iter() {
-                let containing_bags = self.search_for_all_bags_that_contain_bag(&*item);
-
-                // println!("{:?} contain {}", &containing_bags, item);
-                output_set = output_set.union(&containing_bags).map(|x| *x).collect();
-                interim_set = interim_set.union(&containing_bags).map(|x| *x).collect()
-            }
-            if interim_set.is_empty() {
-                break;
-            } else {
-                holding_set = interim_set
-            }
-        }
+        Self::reachable_from(&self.contained_by, bag_type)
+    }
 
-        output_set
+    fn search_for_all_bags_eventually_inside(&self, bag_type: &str) -> HashSet<&'static str> {
+        Self::reachable_from(&self.contains, bag_type)
     }
 
-    fn count_total_number_of_bags_from(&self, target_bag: &'static str) -> usize {
-        let target = self.rule_map.get(target_bag).unwrap();
+    // the number of bags a single target_bag amounts to, itself included. The memo means a shared
+    // sub-bag's total is only worked out once, it still counts towards every bag that holds it
+    fn count_with_memo(
+        &self,
+        target_bag: &'static str,
+        memo: &mut HashMap<&'static str, usize>,
+        path: &mut Vec<&'static str>,
+    ) -> Result<usize, CountError> {
+        if let Some(&total) = memo.get(target_bag) {
+            return Ok(total);
+        }
+        check_for_cycle(path, target_bag)?;
 
-        let mut sum = 1;
+        let target = self
+            .rule_map
+            .get(target_bag)
+            .ok_or(CountError::MissingRule(target_bag))?;
 
+        path.push(target_bag);
+        let mut sum: usize = 1;
         for child in target.containing_children.iter() {
-            sum += child.count * self.count_total_number_of_bags_from(child.name)
+            sum = self
+                .count_with_memo(child.name, memo, path)?
+                .checked_mul(child.count)
+                .and_then(|inside| sum.checked_add(inside))
+                .ok_or(CountError::Overflow(target_bag))?;
         }
 
-        sum
+        path.pop();
+        memo.insert(target_bag, sum);
+        Ok(sum)
+    }
+
+    fn try_count_number_of_bags_nested_in(
+        &self,
+        target_bag: &'static str,
+    ) -> Result<usize, CountError> {
+        Ok(self.count_with_memo(target_bag, &mut HashMap::new(), &mut vec![])? - 1)
+        // subtract the starting bag
+    }
+
+    /// How many of each bag type end up inside target_bag
+    fn count_bags_nested_in_by_type(
+        &self,
+        target_bag: &'static str,
+    ) -> Result<HashMap<&'static str, usize>, CountError> {
+        // order the bags so every bag comes before anything it holds, then push the multiplier for
+        // each bag down to its children
+        let mut order = vec![];
+        let mut visited = HashSet::new();
+        self.post_order(target_bag, &mut visited, &mut vec![], &mut order)?;
+
+        let mut breakdown = HashMap::new();
+        breakdown.insert(target_bag, 1usize);
+        for &bag in order.iter().rev() {
+            let multiplier = breakdown[bag];
+            for edge in Self::edges(&self.contains, bag) {
+                let nested = multiplier
+                    .checked_mul(edge.count)
+                    .ok_or(CountError::Overflow(bag))?;
+                let entry = breakdown.entry(edge.bag).or_insert(0);
+                *entry = entry
+                    .checked_add(nested)
+                    .ok_or(CountError::Overflow(edge.bag))?;
+            }
+        }
+
+        breakdown.remove(target_bag);
+        Ok(breakdown)
+    }
+
+    fn post_order(
+        &self,
+        bag: &'static str,
+        visited: &mut HashSet<&'static str>,
+        path: &mut Vec<&'static str>,
+        order: &mut Vec<&'static str>,
+    ) -> Result<(), CountError> {
+        check_for_cycle(path, bag)?;
+        if !visited.insert(bag) {
+            return Ok(());
+        }
+        if !self.rule_map.contains_key(bag) {
+            return Err(CountError::MissingRule(bag));
+        }
+
+        path.push(bag);
+        for edge in Self::edges(&self.contains, bag) {
+            self.post_order(edge.bag, visited, path, order)?;
+        }
+        path.pop();
+        order.push(bag);
+        Ok(())
+    }
+
+    fn count_total_number_of_bags_from(&self, target_bag: &'static str) -> usize {
+        self.count_with_memo(target_bag, &mut HashMap::new(), &mut vec![])
+            .unwrap_or_else(|error| panic!("{}", error))
     }
 
     fn count_number_of_bags_nested_in(&self, target_bag: &'static str) -> usize {
@@ -122,11 +431,453 @@ impl RuleSearch {
     }
 }
 
+#[derive(Debug, PartialEq)]
+enum RuleIssue {
+    // a bag that eventually contains itself, listed from the first bag back around to it
+    Cycle(Vec<&'static str>),
+    UndefinedBag {
+        bag: &'static str,
+        referenced_by: &'static str,
+    },
+    DuplicateRule(&'static str),
+    // a bag that neither holds nor ends up inside the bag we're validating around
+    Unreachable(&'static str),
+}
+
+impl fmt::Display for RuleIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleIssue::Cycle(path) => write!(f, "cycle: {}", path.join(" -> ")),
+            RuleIssue::UndefinedBag { bag, referenced_by } => write!(
+                f,
+                "{} bags are held by {} bags but have no rule",
+                bag, referenced_by
+            ),
+            RuleIssue::DuplicateRule(bag) => write!(f, "{} bags have more than one rule", bag),
+            RuleIssue::Unreachable(bag) => write!(f, "{} bags are unreachable", bag),
+        }
+    }
+}
+
+impl RuleSearch {
+    // every defined bag in a stable order so reports don't depend on hash ordering
+    fn sorted_bags(&self) -> Vec<&'static str> {
+        let mut bags = self.rule_map.keys().copied().collect::<Vec<&'static str>>();
+        bags.sort_unstable();
+        bags
+    }
+
+    /// Checks the rules for anything that would trip up the searches. Reachability only makes
+    /// sense from somewhere, so a root is required and every bag that neither holds it nor sits
+    /// inside it is reported as unreachable.
+    fn validate(&self, root: &'static str) -> Vec<RuleIssue> {
+        let mut issues = vec![];
+
+        let mut finished = HashSet::new();
+        for bag in self.sorted_bags() {
+            self.find_cycles(bag, &mut finished, &mut vec![], &mut issues);
+        }
+
+        for bag in self.sorted_bags() {
+            for edge in Self::edges(&self.contains, bag) {
+                if !self.rule_map.contains_key(edge.bag) {
+                    issues.push(RuleIssue::UndefinedBag {
+                        bag: edge.bag,
+                        referenced_by: bag,
+                    });
+                }
+            }
+        }
+
+        let mut duplicates = self.duplicate_rules.clone();
+        duplicates.sort_unstable();
+        duplicates.dedup();
+        issues.extend(duplicates.into_iter().map(RuleIssue::DuplicateRule));
+
+        let related = self.search_for_all_bags_that_can_eventually_contain(root);
+        let inside = self.search_for_all_bags_eventually_inside(root);
+        issues.extend(
+            self.sorted_bags()
+                .into_iter()
+                .filter(|&bag| bag != root && !related.contains(bag) && !inside.contains(bag))
+                .map(RuleIssue::Unreachable),
+        );
+
+        issues
+    }
+
+    // depth first search that reports each edge leading back onto the current path
+    fn find_cycles(
+        &self,
+        bag: &'static str,
+        finished: &mut HashSet<&'static str>,
+        path: &mut Vec<&'static str>,
+        issues: &mut Vec<RuleIssue>,
+    ) {
+        if finished.contains(bag) {
+            return;
+        }
+        if let Err(CountError::Cycle(cycle)) = check_for_cycle(path, bag) {
+            issues.push(RuleIssue::Cycle(cycle));
+            return;
+        }
+
+        path.push(bag);
+        for edge in Self::edges(&self.contains, bag) {
+            self.find_cycles(edge.bag, finished, path, issues);
+        }
+        path.pop();
+        finished.insert(bag);
+    }
+}
+
+/// Which part of the bag graph to export
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ExportScope {
+    All,
+    // the bag and everything it ends up holding
+    DescendantsOf(&'static str),
+    // the bag and everything that can end up holding it
+    AncestorsOf(&'static str),
+}
+
+#[derive(Debug, Copy, Clone)]
+struct ExportOptions {
+    scope: ExportScope,
+    highlight: Option<&'static str>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            scope: ExportScope::All,
+            highlight: None,
+        }
+    }
+}
+
+// mermaid node ids can't contain spaces
+fn mermaid_id(bag: &str) -> String {
+    bag.replace(' ', "_")
+}
+
+impl RuleSearch {
+    // the bags and parent -> child edges in scope, sorted so the output is stable
+    fn export_graph(&self, scope: ExportScope) -> (Vec<&'static str>, Vec<(&'static str, Edge)>) {
+        let nodes: HashSet<&'static str> = match scope {
+            ExportScope::All => self
+                .rule_map
+                .keys()
+                .copied()
+                .chain(self.contained_by.keys().copied())
+                .collect(),
+            ExportScope::DescendantsOf(bag) => {
+                let mut nodes = self.search_for_all_bags_eventually_inside(bag);
+                nodes.insert(bag);
+                nodes
+            }
+            ExportScope::AncestorsOf(bag) => {
+                let mut nodes = self.search_for_all_bags_that_can_eventually_contain(bag);
+                nodes.insert(bag);
+                nodes
+            }
+        };
+
+        let mut nodes = nodes.into_iter().collect::<Vec<&'static str>>();
+        nodes.sort_unstable();
+
+        let mut edges = vec![];
+        for &bag in nodes.iter() {
+            for &edge in Self::edges(&self.contains, bag) {
+                if nodes.binary_search(&edge.bag).is_ok() {
+                    edges.push((bag, edge));
+                }
+            }
+        }
+        edges.sort_unstable_by_key(|(bag, edge)| (*bag, edge.bag));
+
+        (nodes, edges)
+    }
+
+    /// Graphviz source for the bag graph, edges are labelled with how many bags are held
+    fn to_dot(&self, options: &ExportOptions) -> String {
+        let (nodes, edges) = self.export_graph(options.scope);
+        let mut output = String::from("digraph bags {\n");
+
+        for bag in nodes {
+            if Some(bag) == options.highlight {
+                output += &format!("    \"{}\" [style=filled, fillcolor=gold];\n", bag);
+            } else {
+                output += &format!("    \"{}\";\n", bag);
+            }
+        }
+        for (bag, edge) in edges {
+            output += &format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                bag, edge.bag, edge.count
+            );
+        }
+
+        output + "}\n"
+    }
+
+    /// Mermaid flowchart source for the bag graph
+    fn to_mermaid(&self, options: &ExportOptions) -> String {
+        let (nodes, edges) = self.export_graph(options.scope);
+        let mut output = String::from("graph TD\n");
+
+        for bag in nodes.iter() {
+            output += &format!("    {}[\"{}\"]\n", mermaid_id(bag), bag);
+        }
+        for (bag, edge) in edges {
+            output += &format!(
+                "    {} -->|{}| {}\n",
+                mermaid_id(bag),
+                edge.count,
+                mermaid_id(edge.bag)
+            );
+        }
+        // styling a bag outside the scope would make mermaid draw it as a stray node
+        if let Some(bag) = options.highlight.filter(|bag| nodes.contains(bag)) {
+            output += &format!("    style {} fill:#ffd700\n", mermaid_id(bag));
+        }
+
+        output
+    }
+}
+
+const SHELL_HELP: &str = "\
+contains <bag>        bags directly inside <bag>
+ancestors <bag>       every bag that can end up holding <bag>
+count <bag>           total bags nested inside <bag>
+path <from> -> <to>   how <to> ends up inside <from>
+depth <bag>           how many levels of bags are nested inside <bag>
+help                  show this message
+quit                  leave the shell";
+
+impl RuleSearch {
+    // finds the name as it appears in the rules so it can be used with the 'static searches
+    fn resolve_bag(&self, name: &str) -> Result<&'static str, Box<dyn Error>> {
+        let name = name.trim();
+        self.rule_map
+            .get_key_value(name)
+            .map(|(&bag, _)| bag)
+            .or_else(|| self.contained_by.get_key_value(name).map(|(&bag, _)| bag))
+            .ok_or_else(|| format!("No bag called {:?}", name).into())
+    }
+
+    /// The chain of bags from `from` down to `to` with the number held at each step
+    fn path_between(&self, from: &'static str, to: &'static str) -> Option<Vec<Edge>> {
+        let mut came_from: HashMap<&'static str, (&'static str, Edge)> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(from);
+
+        while let Some(bag) = queue.pop_front() {
+            if bag == to {
+                let mut path = vec![];
+                let mut current = to;
+                while current != from {
+                    let (parent, edge) = came_from[current];
+                    path.push(edge);
+                    current = parent;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &edge in Self::edges(&self.contains, bag) {
+                if edge.bag != from && !came_from.contains_key(edge.bag) {
+                    came_from.insert(edge.bag, (bag, edge));
+                    queue.push_back(edge.bag);
+                }
+            }
+        }
+
+        None
+    }
+
+    // the longest chain of bags inside bag, a bag holding nothing has a depth of 0
+    fn nesting_depth(
+        &self,
+        bag: &'static str,
+        memo: &mut HashMap<&'static str, usize>,
+        path: &mut Vec<&'static str>,
+    ) -> Result<usize, CountError> {
+        if let Some(&depth) = memo.get(bag) {
+            return Ok(depth);
+        }
+        check_for_cycle(path, bag)?;
+
+        path.push(bag);
+        let mut depth = 0;
+        for edge in Self::edges(&self.contains, bag) {
+            depth = depth.max(self.nesting_depth(edge.bag, memo, path)? + 1);
+        }
+        path.pop();
+
+        memo.insert(bag, depth);
+        Ok(depth)
+    }
+
+    fn sorted_names(bags: impl IntoIterator<Item = &'static str>) -> String {
+        let mut bags = bags.into_iter().collect::<Vec<&'static str>>();
+        bags.sort_unstable();
+        bags.join("\n")
+    }
+
+    /// Answers a single shell command, eg: path shiny gold -> dotted black
+    fn run_query(&self, command: &str) -> Result<String, Box<dyn Error>> {
+        let command = command.trim();
+        let (name, argument) = command.split_at(command.find(' ').unwrap_or(command.len()));
+
+        match name {
+            "contains" => {
+                let bag = self.resolve_bag(argument)?;
+                let edges = Self::edges(&self.contains, bag);
+                if edges.is_empty() {
+                    return Ok(format!("{} bags contain no other bags", bag));
+                }
+                Ok(edges
+                    .iter()
+                    .map(|edge| format!("{} {}", edge.count, edge.bag))
+                    .collect::<Vec<String>>()
+                    .join("\n"))
+            }
+            "ancestors" => {
+                let bag = self.resolve_bag(argument)?;
+                let ancestors = self.search_for_all_bags_that_can_eventually_contain(bag);
+                Ok(format!(
+                    "{} bags can hold {}\n{}",
+                    ancestors.len(),
+                    bag,
+                    Self::sorted_names(ancestors)
+                )
+                .trim_end()
+                .to_string())
+            }
+            "count" => {
+                let bag = self.resolve_bag(argument)?;
+                Ok(self.try_count_number_of_bags_nested_in(bag)?.to_string())
+            }
+            "path" => {
+                let (from, to) = argument
+                    .split_once("->")
+                    .ok_or("Usage: path <from> -> <to>")?;
+                let (from, to) = (self.resolve_bag(from)?, self.resolve_bag(to)?);
+                let path = self
+                    .path_between(from, to)
+                    .ok_or_else(|| format!("{} bags never end up inside {} bags", to, from))?;
+
+                let mut total: usize = 1;
+                let mut output = from.to_string();
+                for edge in path {
+                    total = total
+                        .checked_mul(edge.count)
+                        .ok_or(CountError::Overflow(from))?;
+                    output += &format!(" -> {} {}", edge.count, edge.bag);
+                }
+                Ok(format!("{} ({} {} in total)", output, total, to))
+            }
+            "depth" => {
+                let bag = self.resolve_bag(argument)?;
+                Ok(self
+                    .nesting_depth(bag, &mut HashMap::new(), &mut vec![])?
+                    .to_string())
+            }
+            "help" => Ok(SHELL_HELP.to_string()),
+            _ => Err(format!("Unknown command {:?}, try help", name).into()),
+        }
+    }
+
+    fn run_shell(&self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        write!(output, "> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            match line.trim() {
+                "" => {}
+                "quit" | "exit" => break,
+                command => match self.run_query(command) {
+                    Ok(answer) => writeln!(output, "{}", answer)?,
+                    Err(error) => writeln!(output, "error: {}", error)?,
+                },
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+// every rule in a file, errors point at the one based line they were found on
+fn parse_rules(source: &'static str) -> Result<Vec<InputLine>, Box<dyn Error>> {
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            try_parse_input_line(line)
+                .map_err(|error| format!("line {}, {}", index + 1, error).into())
+        })
+        .collect()
+}
+
+/// Loads a rules file and answers questions about it from stdin until quit
+pub fn rule_shell(rules_path: &str) -> Result<(), Box<dyn Error>> {
+    // the rules borrow from the source for the rest of the program, so let it live that long
+    let source: &'static str = Box::leak(fs::read_to_string(rules_path)?.into_boxed_str());
+    let search = RuleSearch::from_input_lines(parse_rules(source)?.into_iter());
+
+    println!(
+        "Loaded {} rules from {}, type help for commands",
+        search.rule_map.len(),
+        rules_path
+    );
+    search.run_shell(io::stdin().lock(), io::stdout())?;
+    Ok(())
+}
+
+// errors if bag is already on the path we took to get here
+fn check_for_cycle(path: &[&'static str], bag: &'static str) -> Result<(), CountError> {
+    match path.iter().position(|&visited| visited == bag) {
+        Some(start) => {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(bag);
+            Err(CountError::Cycle(cycle))
+        }
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum CountError {
+    // a bag is referenced as a child but never has a rule of its own
+    MissingRule(&'static str),
+    // the count for this bag does not fit in a usize
+    Overflow(&'static str),
+    // a bag that eventually contains itself, listed from the first bag back around to it
+    Cycle(Vec<&'static str>),
+}
+
+impl fmt::Display for CountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CountError::MissingRule(bag) => write!(f, "No rule defines what {} bags contain", bag),
+            CountError::Overflow(bag) => write!(f, "Counting inside {} bags overflowed", bag),
+            CountError::Cycle(path) => write!(f, "Bags contain themselves: {}", path.join(" -> ")),
+        }
+    }
+}
+
+impl Error for CountError {}
+
 #[cfg(test)]
 mod tests {
-    use crate::day_seven::{parse_input_line, InputChild, InputLine, RuleSearch};
-    
-    
+    use crate::day_seven::{
+        parse_input_line, parse_rules, try_parse_input_line, CountError, ExportOptions,
+        ExportScope, InputChild, InputLine, RuleIssue, RuleSearch,
+    };
+    use indoc::indoc;
 
     #[test]
     fn it_can_parse_an_input_rule() {
@@ -185,6 +936,31 @@ mod tests {
         assert_eq!(finished_set.len(), 4);
     }
 
+    #[test]
+    fn search_struct_can_find_all_bags_inside_a_bag() {
+        let search_struct = RuleSearch::from_input_lines(
+            include_str!("inputs/day_seven.test.txt")
+                .lines()
+                .map(parse_input_line),
+        );
+
+        let mut inside = search_struct
+            .search_for_all_bags_eventually_inside("shiny gold")
+            .into_iter()
+            .collect::<Vec<&str>>();
+        inside.sort_unstable();
+        assert_eq!(
+            inside,
+            vec!["dark olive", "dotted black", "faded blue", "vibrant plum"]
+        );
+        assert!(search_struct
+            .search_for_all_bags_eventually_inside("faded blue")
+            .is_empty());
+        assert!(search_struct
+            .search_for_all_bags_that_can_eventually_contain("light red")
+            .is_empty());
+    }
+
     #[test]
     fn production_run_a() {
         let search_struct = RuleSearch::from_input_lines(
@@ -233,4 +1009,413 @@ mod tests {
 
         assert_eq!(finished_set, 211);
     }
+
+    #[test]
+    fn counts_can_be_broken_down_by_bag_type() {
+        let search_struct = RuleSearch::from_input_lines(
+            include_str!("inputs/day_seven.test.txt")
+                .lines()
+                .map(parse_input_line),
+        );
+        let breakdown = search_struct
+            .count_bags_nested_in_by_type("shiny gold")
+            .unwrap();
+
+        assert_eq!(breakdown.len(), 4);
+        assert_eq!(breakdown["dark olive"], 1);
+        assert_eq!(breakdown["vibrant plum"], 2);
+        assert_eq!(breakdown["faded blue"], 13);
+        assert_eq!(breakdown["dotted black"], 16);
+        assert_eq!(
+            breakdown.values().sum::<usize>(),
+            search_struct
+                .try_count_number_of_bags_nested_in("shiny gold")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn production_breakdown_matches_count() {
+        let search_struct = RuleSearch::from_input_lines(
+            include_str!("inputs/day_seven.txt")
+                .lines()
+                .map(parse_input_line),
+        );
+        let breakdown = search_struct
+            .count_bags_nested_in_by_type("shiny gold")
+            .unwrap();
+
+        assert_eq!(breakdown.values().sum::<usize>(), 12414);
+        assert_eq!(
+            search_struct.try_count_number_of_bags_nested_in("shiny gold"),
+            Ok(12414)
+        );
+    }
+
+    #[test]
+    fn missing_rules_are_reported() {
+        let search_struct = RuleSearch::from_input_lines(
+            vec!["shiny gold bags contain 2 dark red bags."]
+                .into_iter()
+                .map(parse_input_line),
+        );
+
+        let error = search_struct
+            .try_count_number_of_bags_nested_in("shiny gold")
+            .unwrap_err();
+        assert_eq!(error, CountError::MissingRule("dark red"));
+        assert_eq!(
+            error.to_string(),
+            "No rule defines what dark red bags contain"
+        );
+        assert_eq!(
+            search_struct.count_bags_nested_in_by_type("shiny gold"),
+            Err(CountError::MissingRule("dark red"))
+        );
+    }
+
+    #[test]
+    fn overflowing_counts_are_reported() {
+        let search_struct = RuleSearch::from_input_lines(
+            vec![
+                "a a bags contain 100000 b b bags.",
+                "b b bags contain 100000 c c bags.",
+                "c c bags contain 100000 d d bags.",
+                "d d bags contain 100000 e e bags.",
+                "e e bags contain no other bags.",
+            ]
+            .into_iter()
+            .map(parse_input_line),
+        );
+
+        assert!(search_struct
+            .try_count_number_of_bags_nested_in("b b")
+            .is_ok());
+        assert!(matches!(
+            search_struct.try_count_number_of_bags_nested_in("a a"),
+            Err(CountError::Overflow(_))
+        ));
+        assert!(matches!(
+            search_struct.count_bags_nested_in_by_type("a a"),
+            Err(CountError::Overflow(_))
+        ));
+    }
+
+    #[test]
+    fn cycles_are_reported_instead_of_recursing_forever() {
+        let search_struct = RuleSearch::from_input_lines(
+            vec![
+                "shiny gold bags contain 1 dark red bag.",
+                "dark red bags contain 2 pale blue bags.",
+                "pale blue bags contain 1 shiny gold bag.",
+            ]
+            .into_iter()
+            .map(parse_input_line),
+        );
+        let cycle = vec!["shiny gold", "dark red", "pale blue", "shiny gold"];
+        assert_eq!(
+            search_struct.search_for_all_bags_that_can_eventually_contain("shiny gold"),
+            vec!["dark red", "pale blue"].into_iter().collect()
+        );
+
+        assert_eq!(
+            search_struct.try_count_number_of_bags_nested_in("shiny gold"),
+            Err(CountError::Cycle(cycle.clone()))
+        );
+        assert_eq!(
+            search_struct.count_bags_nested_in_by_type("shiny gold"),
+            Err(CountError::Cycle(cycle))
+        );
+        assert_eq!(
+            search_struct.validate("shiny gold"),
+            vec![RuleIssue::Cycle(vec![
+                "dark red",
+                "pale blue",
+                "shiny gold",
+                "dark red"
+            ])]
+        );
+    }
+
+    #[test]
+    fn validator_reports_inconsistent_rules() {
+        let search_struct = RuleSearch::from_input_lines(
+            vec![
+                "shiny gold bags contain 1 dark red bag, 2 faded blue bags.",
+                "dark red bags contain no other bags.",
+                "light red bags contain 3 shiny gold bags.",
+                "dark red bags contain 1 posh lime bag.",
+                "dull tan bags contain no other bags.",
+            ]
+            .into_iter()
+            .map(parse_input_line),
+        );
+        let issues = search_struct.validate("shiny gold");
+
+        assert_eq!(
+            issues,
+            vec![
+                RuleIssue::UndefinedBag {
+                    bag: "posh lime",
+                    referenced_by: "dark red"
+                },
+                RuleIssue::UndefinedBag {
+                    bag: "faded blue",
+                    referenced_by: "shiny gold"
+                },
+                RuleIssue::DuplicateRule("dark red"),
+                RuleIssue::Unreachable("dull tan"),
+            ]
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            "posh lime bags are held by dark red bags but have no rule"
+        );
+    }
+
+    #[test]
+    fn production_rules_are_consistent() {
+        let search_struct = RuleSearch::from_input_lines(
+            include_str!("inputs/day_seven.txt")
+                .lines()
+                .map(parse_input_line),
+        );
+
+        // plenty of bags have nothing to do with shiny gold, but the rules themselves are sound
+        assert!(search_struct
+            .validate("shiny gold")
+            .iter()
+            .all(|issue| matches!(issue, RuleIssue::Unreachable(_))));
+    }
+
+    #[test]
+    fn bag_graph_exports_to_dot() {
+        let search_struct = RuleSearch::from_input_lines(
+            include_str!("inputs/day_seven.test.txt")
+                .lines()
+                .map(parse_input_line),
+        );
+        let dot = search_struct.to_dot(&ExportOptions {
+            scope: ExportScope::DescendantsOf("shiny gold"),
+            highlight: Some("shiny gold"),
+        });
+
+        assert_eq!(
+            dot,
+            indoc! {r#"
+                digraph bags {
+                    "dark olive";
+                    "dotted black";
+                    "faded blue";
+                    "shiny gold" [style=filled, fillcolor=gold];
+                    "vibrant plum";
+                    "dark olive" -> "dotted black" [label="4"];
+                    "dark olive" -> "faded blue" [label="3"];
+                    "shiny gold" -> "dark olive" [label="1"];
+                    "shiny gold" -> "vibrant plum" [label="2"];
+                    "vibrant plum" -> "dotted black" [label="6"];
+                    "vibrant plum" -> "faded blue" [label="5"];
+                }
+            "#}
+        );
+
+        let everything = search_struct.to_dot(&ExportOptions::default());
+        assert_eq!(everything.matches(" -> ").count(), 13);
+        assert_eq!(everything.matches("\";\n").count(), 9);
+    }
+
+    #[test]
+    fn bag_graph_exports_ancestors_to_mermaid() {
+        let search_struct = RuleSearch::from_input_lines(
+            include_str!("inputs/day_seven.test.txt")
+                .lines()
+                .map(parse_input_line),
+        );
+        let mermaid = search_struct.to_mermaid(&ExportOptions {
+            scope: ExportScope::AncestorsOf("shiny gold"),
+            highlight: Some("shiny gold"),
+        });
+
+        assert_eq!(
+            mermaid,
+            indoc! {r#"
+                graph TD
+                    bright_white["bright white"]
+                    dark_orange["dark orange"]
+                    light_red["light red"]
+                    muted_yellow["muted yellow"]
+                    shiny_gold["shiny gold"]
+                    bright_white -->|1| shiny_gold
+                    dark_orange -->|3| bright_white
+                    dark_orange -->|4| muted_yellow
+                    light_red -->|1| bright_white
+                    light_red -->|2| muted_yellow
+                    muted_yellow -->|2| shiny_gold
+                    style shiny_gold fill:#ffd700
+            "#}
+        );
+
+        let mermaid = search_struct.to_mermaid(&ExportOptions {
+            scope: ExportScope::AncestorsOf("shiny gold"),
+            highlight: Some("dotted black"),
+        });
+        assert!(!mermaid.contains("dotted_black"));
+    }
+
+    #[test]
+    fn rules_accept_pluralisation_and_missing_periods() {
+        let expected = InputLine {
+            target_bag: "bright white",
+            containing_children: vec![InputChild {
+                count: 1,
+                name: "shiny gold",
+            }],
+        };
+
+        assert_eq!(
+            try_parse_input_line("bright white bag contains 1 shiny gold bags"),
+            Ok(expected)
+        );
+        assert_eq!(
+            try_parse_input_line("faded blue bags contain no other bag"),
+            Ok(InputLine {
+                target_bag: "faded blue",
+                containing_children: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn rule_errors_point_at_the_problem() {
+        let error = |line| try_parse_input_line(line).unwrap_err().to_string();
+
+        assert_eq!(
+            error("light red bags hold 1 bright white bag."),
+            "column 16: expected \"contain\" but found \"hold\""
+        );
+        assert_eq!(
+            error("light red bags contain 1 bright white."),
+            "column 38: expected \"bag\" or \"bags\" but found \".\""
+        );
+        assert_eq!(
+            error("light red bags contain bright white bags."),
+            "column 24: expected a count but found \"bright\""
+        );
+        assert_eq!(
+            error("light red bags contain 1 bright white bag,"),
+            "column 43: expected a count but the rule ended"
+        );
+        assert_eq!(
+            error("light red bags contain 2 dull tan bags. 3"),
+            "column 41: expected the end of the rule but found \"3\""
+        );
+        assert_eq!(
+            error("light red bags contain 2 dull-tan bags."),
+            "column 30: unexpected character '-'"
+        );
+        assert_eq!(
+            error("bags contain no other bags."),
+            "column 1: expected a bag name but found \"bags\""
+        );
+    }
+
+    #[test]
+    fn rules_round_trip_to_canonical_text() {
+        for line in include_str!("inputs/day_seven.txt").lines() {
+            assert_eq!(parse_input_line(line).to_string(), line);
+        }
+
+        assert_eq!(
+            parse_input_line("light red bag contain 1 bright white bags, 2 muted yellow bag")
+                .to_string(),
+            "light red bags contain 1 bright white bag, 2 muted yellow bags."
+        );
+    }
+
+    #[test]
+    fn shell_answers_queries() {
+        let search_struct = RuleSearch::from_input_lines(
+            include_str!("inputs/day_seven.test.txt")
+                .lines()
+                .map(parse_input_line),
+        );
+        let query = |command| search_struct.run_query(command).unwrap();
+
+        assert_eq!(query("contains shiny gold"), "1 dark olive\n2 vibrant plum");
+        assert_eq!(
+            query("contains faded blue"),
+            "faded blue bags contain no other bags"
+        );
+        assert_eq!(
+            query("ancestors shiny gold"),
+            "4 bags can hold shiny gold\nbright white\ndark orange\nlight red\nmuted yellow"
+        );
+        assert_eq!(query("count shiny gold"), "32");
+        assert_eq!(
+            query("path light red -> dotted black"),
+            "light red -> 1 bright white -> 1 shiny gold -> 1 dark olive -> 4 dotted black (4 dotted black in total)"
+        );
+        assert_eq!(query("depth light red"), "4");
+        assert_eq!(query("depth faded blue"), "0");
+
+        assert!(search_struct.run_query("count mauve").is_err());
+        assert!(search_struct
+            .run_query("path faded blue -> shiny gold")
+            .is_err());
+        assert!(search_struct.run_query("path shiny gold").is_err());
+        assert!(search_struct.run_query("explode").is_err());
+
+        let huge = RuleSearch::from_input_lines(
+            vec![
+                "light red bags contain 18446744073709551615 dark red bags.",
+                "dark red bags contain 2 faded blue bags.",
+                "faded blue bags contain no other bags.",
+            ]
+            .into_iter()
+            .map(parse_input_line),
+        );
+        assert_eq!(
+            huge.run_query("path light red -> faded blue")
+                .unwrap_err()
+                .to_string(),
+            "Counting inside light red bags overflowed"
+        );
+    }
+
+    #[test]
+    fn rule_files_report_the_line_of_a_bad_rule() {
+        let rules =
+            parse_rules("light red bags contain no other bags.\n\n[package]\n").unwrap_err();
+        assert_eq!(
+            rules.to_string(),
+            "line 3, column 1: unexpected character '['"
+        );
+        assert_eq!(
+            parse_rules(include_str!("inputs/day_seven.test.txt"))
+                .unwrap()
+                .len(),
+            9
+        );
+    }
+
+    #[test]
+    fn shell_reads_commands_until_quit() {
+        let search_struct = RuleSearch::from_input_lines(
+            include_str!("inputs/day_seven.test.txt")
+                .lines()
+                .map(parse_input_line),
+        );
+        let mut output = vec![];
+        search_struct
+            .run_shell(
+                "count shiny gold\n\nwhat\nquit\ncount shiny gold\n".as_bytes(),
+                &mut output,
+            )
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "> 32\n> > error: Unknown command \"what\", try help\n> "
+        );
+    }
 }
@@ -1,15 +1,17 @@
-use std::collections::{HashMap, HashSet};
-use std::str::Lines;
+use crate::utils::Records;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
 
 #[derive(Debug)]
 struct GroupIterator {
-    source: Lines<'static>,
+    source: Records<'static>,
 }
 
 impl GroupIterator {
     fn new(source: &'static str) -> Self {
         Self {
-            source: source.lines(),
+            source: Records::new(source),
         }
     }
 }
@@ -18,25 +20,8 @@ impl Iterator for GroupIterator {
     type Item = Group;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut group_members = vec![];
-        loop {
-            let next = self.source.next();
-            if next.is_none() && group_members.len() > 0 {
-                break;
-            } else if next.is_none() {
-                return None;
-            }
-
-            let next = next.unwrap();
-
-            if next.is_empty() {
-                break;
-            }
-            group_members.push(next);
-        }
-
-        Some(Group {
-            lines: group_members,
+        self.source.next().map(|record| Group {
+            lines: record.lines,
             index: 0,
         })
     }
@@ -59,6 +44,254 @@ impl Iterator for Group {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct QuestionStatistics {
+    question: char,
+    // people who answered yes
+    people: usize,
+    // groups where at least one person answered yes
+    groups: usize,
+    // groups where every person answered yes
+    unanimous_groups: usize,
+}
+
+/// Survey wide numbers for every question along with how large the groups were
+#[derive(Debug, PartialEq)]
+struct SurveyReport {
+    total_people: usize,
+    total_groups: usize,
+    questions: Vec<QuestionStatistics>,
+    // group size to the number of groups of that size
+    group_sizes: BTreeMap<usize, usize>,
+}
+
+impl SurveyReport {
+    fn from_groups(groups: impl Iterator<Item = Group>) -> Self {
+        let mut report = Self {
+            total_people: 0,
+            total_groups: 0,
+            questions: ('a'..='z')
+                .map(|question| QuestionStatistics {
+                    question,
+                    people: 0,
+                    groups: 0,
+                    unanimous_groups: 0,
+                })
+                .collect(),
+            group_sizes: BTreeMap::new(),
+        };
+
+        for group in groups {
+            report.total_people += group.size();
+            report.total_groups += 1;
+            *report.group_sizes.entry(group.size()).or_insert(0) += 1;
+
+            let unanimous = everyone_answered(&group);
+            for (statistics, &count) in report.questions.iter_mut().zip(group.yes_counts().iter()) {
+                statistics.people += count;
+                if count > 0 {
+                    statistics.groups += 1;
+                }
+                if unanimous.contains(statistics.question) {
+                    statistics.unanimous_groups += 1;
+                }
+            }
+        }
+
+        report
+    }
+
+    // the fraction of all groups where everyone answered yes to this question
+    fn unanimous_fraction(&self, statistics: &QuestionStatistics) -> f64 {
+        if self.total_groups == 0 {
+            0.0
+        } else {
+            statistics.unanimous_groups as f64 / self.total_groups as f64
+        }
+    }
+
+    fn questions_csv(&self) -> String {
+        let mut output =
+            String::from("question,people,groups,unanimous_groups,unanimous_fraction\n");
+        for statistics in self.questions.iter() {
+            output += &format!(
+                "{},{},{},{},{:.4}\n",
+                statistics.question,
+                statistics.people,
+                statistics.groups,
+                statistics.unanimous_groups,
+                self.unanimous_fraction(statistics)
+            );
+        }
+        output
+    }
+
+    fn group_sizes_csv(&self) -> String {
+        let mut output = String::from("group_size,groups\n");
+        for (size, groups) in self.group_sizes.iter() {
+            output += &format!("{},{}\n", size, groups);
+        }
+        output
+    }
+
+    fn to_json(&self) -> String {
+        let questions = self
+            .questions
+            .iter()
+            .map(|statistics| {
+                format!(
+                    r#"{{"question":"{}","people":{},"groups":{},"unanimous_groups":{},"unanimous_fraction":{:.4}}}"#,
+                    statistics.question,
+                    statistics.people,
+                    statistics.groups,
+                    statistics.unanimous_groups,
+                    self.unanimous_fraction(statistics)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let group_sizes = self
+            .group_sizes
+            .iter()
+            .map(|(size, groups)| format!(r#""{}":{}"#, size, groups))
+            .collect::<Vec<String>>()
+            .join(",");
+
+        format!(
+            r#"{{"total_people":{},"total_groups":{},"questions":[{}],"group_sizes":{{{}}}}}"#,
+            self.total_people, self.total_groups, questions, group_sizes
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ParseMode {
+    // every character must be in the alphabet and appear at most once per person
+    Strict,
+    // case is normalised, whitespace is skipped and repeated answers are collapsed
+    Lenient,
+}
+
+#[derive(Debug, PartialEq)]
+enum SurveyError {
+    InvalidAnswer {
+        line: usize,
+        column: usize,
+        answer: char,
+    },
+    DuplicateAnswer {
+        line: usize,
+        column: usize,
+        answer: char,
+    },
+}
+
+impl fmt::Display for SurveyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SurveyError::InvalidAnswer {
+                line,
+                column,
+                answer,
+            } => write!(f, "{}:{}: {:?} is not a question", line, column, answer),
+            SurveyError::DuplicateAnswer {
+                line,
+                column,
+                answer,
+            } => write!(f, "{}:{}: {:?} was already answered", line, column, answer),
+        }
+    }
+}
+
+impl Error for SurveyError {}
+
+/// A group that has passed validation. Each member is kept as the set of questions they answered.
+#[derive(Debug, PartialEq)]
+struct ValidatedGroup {
+    line_number: usize,
+    members: Vec<AnswerSet>,
+}
+
+impl ValidatedGroup {
+    fn anyone_answered(&self) -> AnswerSet {
+        self.members
+            .iter()
+            .fold(AnswerSet::EMPTY, |acc, &member| acc.union(member))
+    }
+
+    fn everyone_answered(&self) -> AnswerSet {
+        self.members
+            .iter()
+            .fold(AnswerSet::ALL, |acc, &member| acc.intersection(member))
+    }
+}
+
+/// Checks survey input against an answer alphabet instead of accepting any character
+#[derive(Debug, Copy, Clone)]
+struct SurveyParser {
+    alphabet: AnswerSet,
+    mode: ParseMode,
+}
+
+impl SurveyParser {
+    fn new(alphabet: AnswerSet, mode: ParseMode) -> Self {
+        Self { alphabet, mode }
+    }
+
+    fn parse_person(&self, line: usize, source: &str, errors: &mut Vec<SurveyError>) -> AnswerSet {
+        let mut answers = AnswerSet::EMPTY;
+        for (index, answer) in source.chars().enumerate() {
+            let column = index + 1;
+            let answer = match self.mode {
+                ParseMode::Strict => answer,
+                ParseMode::Lenient if answer.is_whitespace() => continue,
+                ParseMode::Lenient => answer.to_ascii_lowercase(),
+            };
+
+            if !self.alphabet.contains(answer) {
+                errors.push(SurveyError::InvalidAnswer {
+                    line,
+                    column,
+                    answer,
+                });
+            } else if answers.contains(answer) && self.mode == ParseMode::Strict {
+                errors.push(SurveyError::DuplicateAnswer {
+                    line,
+                    column,
+                    answer,
+                });
+            }
+            answers.insert(answer);
+        }
+
+        answers.intersection(self.alphabet)
+    }
+
+    // returns every problem in the input rather than stopping at the first one
+    fn parse(&self, source: &str) -> Result<Vec<ValidatedGroup>, Vec<SurveyError>> {
+        let mut errors = vec![];
+        let groups = Records::new(source)
+            .map(|record| ValidatedGroup {
+                line_number: record.line_number,
+                members: record
+                    .lines
+                    .iter()
+                    .enumerate()
+                    .map(|(offset, person)| {
+                        self.parse_person(record.line_number + offset, person, &mut errors)
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(groups)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
 fn determine_answer_set_for_group(group: &mut Group) -> HashSet<char> {
     let mut answer_set = HashSet::new();
     for person in group {
@@ -87,12 +320,171 @@ fn determine_common_answer_set_for_group(group: &mut Group) -> HashSet<char> {
     output_set
 }
 
+/// The set of questions a to z as a bitmask, bit 0 is question a. Characters outside a to z don't
+/// correspond to a question and are ignored.
+#[derive(Copy, Clone, Default, Eq, PartialEq)]
+struct AnswerSet(u32);
+
+impl AnswerSet {
+    const EMPTY: AnswerSet = AnswerSet(0);
+    const ALL: AnswerSet = AnswerSet((1 << 26) - 1);
+
+    fn from_answers(answers: &str) -> Self {
+        answers.chars().collect()
+    }
+
+    fn bit(question: char) -> u32 {
+        if question.is_ascii_lowercase() {
+            1 << (question as u32 - 'a' as u32)
+        } else {
+            0
+        }
+    }
+
+    fn insert(&mut self, question: char) {
+        self.0 |= Self::bit(question)
+    }
+
+    fn contains(&self, question: char) -> bool {
+        let bit = Self::bit(question);
+        bit != 0 && self.0 & bit == bit
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn iter(&self) -> impl Iterator<Item = char> {
+        let bits = self.0;
+        ('a'..='z').filter(move |&question| bits & Self::bit(question) != 0)
+    }
+}
+
+impl std::iter::FromIterator<char> for AnswerSet {
+    fn from_iter<T: IntoIterator<Item = char>>(iter: T) -> Self {
+        let mut set = AnswerSet::EMPTY;
+        for question in iter {
+            set.insert(question);
+        }
+        set
+    }
+}
+
+impl fmt::Debug for AnswerSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl Group {
+    fn size(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn answer_sets(&self) -> impl Iterator<Item = AnswerSet> + '_ {
+        self.lines
+            .iter()
+            .map(|person| AnswerSet::from_answers(person))
+    }
+
+    /// How many members said yes to each question, index 0 is question a
+    fn yes_counts(&self) -> [usize; 26] {
+        let mut counts = [0; 26];
+        for answers in self.answer_sets() {
+            for question in answers.iter() {
+                counts[question as usize - 'a' as usize] += 1;
+            }
+        }
+        counts
+    }
+
+    fn answered_where(&self, predicate: impl Fn(usize) -> bool) -> AnswerSet {
+        ('a'..='z')
+            .zip(self.yes_counts().iter())
+            .filter(|(_, &count)| count > 0 && predicate(count))
+            .map(|(question, _)| question)
+            .collect()
+    }
+
+    fn answered_by_at_least(&self, members: usize) -> AnswerSet {
+        self.answered_where(|count| count >= members)
+    }
+
+    fn answered_by_exactly(&self, members: usize) -> AnswerSet {
+        self.answered_where(|count| count == members)
+    }
+
+    fn answered_by_exactly_one(&self) -> AnswerSet {
+        self.answered_by_exactly(1)
+    }
+
+    // strictly more than half of the group, so a two person group needs both
+    fn answered_by_majority(&self) -> AnswerSet {
+        let size = self.size();
+        self.answered_where(|count| count * 2 > size)
+    }
+}
+
+// runs a group query over every group and keeps the questions that came back for all of them
+fn common_across_groups<'a>(
+    groups: impl IntoIterator<Item = &'a Group>,
+    query: impl Fn(&Group) -> AnswerSet,
+) -> AnswerSet {
+    groups
+        .into_iter()
+        .map(query)
+        .fold(AnswerSet::ALL, AnswerSet::intersection)
+}
+
+// runs a group query over every group and keeps the questions that came back for any of them
+fn seen_across_groups<'a>(
+    groups: impl IntoIterator<Item = &'a Group>,
+    query: impl Fn(&Group) -> AnswerSet,
+) -> AnswerSet {
+    groups
+        .into_iter()
+        .map(query)
+        .fold(AnswerSet::EMPTY, AnswerSet::union)
+}
+
+// questions anyone in the group answered yes to
+fn anyone_answered(group: &Group) -> AnswerSet {
+    group.answer_sets().fold(AnswerSet::EMPTY, AnswerSet::union)
+}
+
+// questions everyone in the group answered yes to
+fn everyone_answered(group: &Group) -> AnswerSet {
+    group
+        .answer_sets()
+        .fold(AnswerSet::ALL, AnswerSet::intersection)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::day_six::{
-        determine_answer_set_for_group, determine_common_answer_set_for_group, Group, GroupIterator,
+        anyone_answered, common_across_groups, determine_answer_set_for_group,
+        determine_common_answer_set_for_group, everyone_answered, seen_across_groups, AnswerSet,
+        Group, GroupIterator, ParseMode, SurveyError, SurveyParser, SurveyReport, ValidatedGroup,
     };
     use indoc::indoc;
+    use std::collections::HashSet;
+    use std::time::Instant;
 
     #[test]
     fn it_can_parse_inputs() {
@@ -208,4 +600,290 @@ mod tests {
 
         assert_eq!(length, 3299);
     }
+
+    #[test]
+    fn answer_sets_support_set_algebra() {
+        let left = AnswerSet::from_answers("abcx");
+        let right = AnswerSet::from_answers("bcy");
+
+        assert_eq!(left.union(right), AnswerSet::from_answers("abcxy"));
+        assert_eq!(left.intersection(right), AnswerSet::from_answers("bc"));
+        assert_eq!(left.difference(right), AnswerSet::from_answers("ax"));
+        assert_eq!(left.len(), 4);
+        assert!(left.contains('x') && !left.contains('y') && !left.contains('A'));
+        assert!(left.difference(left).is_empty());
+        assert_eq!(right.iter().collect::<String>(), "bcy");
+        assert_eq!(AnswerSet::ALL.len(), 26);
+        assert_eq!(format!("{:?}", right), "{'b', 'c', 'y'}");
+    }
+
+    #[test]
+    fn answer_sets_agree_with_hash_sets() {
+        for mut group in GroupIterator::new(include_str!("inputs/day_six.txt")) {
+            let anyone = anyone_answered(&group);
+            let everyone = everyone_answered(&group);
+
+            assert_eq!(
+                anyone.iter().collect::<HashSet<char>>(),
+                determine_answer_set_for_group(&mut group)
+            );
+            group.index = 0;
+            assert_eq!(
+                everyone.iter().collect::<HashSet<char>>(),
+                determine_common_answer_set_for_group(&mut group)
+            );
+        }
+    }
+
+    #[test]
+    fn production_with_answer_sets() {
+        let groups = GroupIterator::new(include_str!("inputs/day_six.txt")).collect::<Vec<Group>>();
+
+        let anyone: usize = groups.iter().map(|g| anyone_answered(g).len()).sum();
+        let everyone: usize = groups.iter().map(|g| everyone_answered(g).len()).sum();
+
+        assert_eq!(anyone, 6542);
+        assert_eq!(everyone, 3299);
+    }
+
+    // cargo test bench_answer_sets -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_answer_sets_against_hash_sets() {
+        const ROUNDS: usize = 200;
+        let mut groups =
+            GroupIterator::new(include_str!("inputs/day_six.txt")).collect::<Vec<Group>>();
+
+        let start = Instant::now();
+        let mut total = 0;
+        for _ in 0..ROUNDS {
+            for group in groups.iter() {
+                total += anyone_answered(group).len() + everyone_answered(group).len();
+            }
+        }
+        let bitset = start.elapsed();
+
+        // the hash set versions walk the group's cursor, so it is rewound instead of copying groups
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            for group in groups.iter_mut() {
+                group.index = 0;
+                total -= determine_answer_set_for_group(group).len();
+                group.index = 0;
+                total -= determine_common_answer_set_for_group(group).len();
+            }
+        }
+        let hashed = start.elapsed();
+
+        assert_eq!(total, 0);
+        println!(
+            "{} rounds: AnswerSet {:?}, HashSet/HashMap {:?}",
+            ROUNDS, bitset, hashed
+        );
+    }
+
+    #[test]
+    fn groups_can_be_queried_by_answer_counts() {
+        let group = Group {
+            index: 0,
+            lines: vec!["abcx", "abc", "ay", "a"],
+        };
+
+        let mut expected = [0; 26];
+        expected[0] = 4;
+        expected[1] = 2;
+        expected[2] = 2;
+        expected[23] = 1;
+        expected[24] = 1;
+        assert_eq!(group.yes_counts(), expected);
+
+        assert_eq!(
+            group.answered_by_at_least(2),
+            AnswerSet::from_answers("abc")
+        );
+        assert_eq!(group.answered_by_at_least(1), anyone_answered(&group));
+        assert_eq!(group.answered_by_at_least(4), everyone_answered(&group));
+        assert_eq!(group.answered_by_exactly(2), AnswerSet::from_answers("bc"));
+        assert_eq!(
+            group.answered_by_exactly_one(),
+            AnswerSet::from_answers("xy")
+        );
+        assert_eq!(group.answered_by_majority(), AnswerSet::from_answers("a"));
+        assert!(group.answered_by_at_least(5).is_empty());
+    }
+
+    #[test]
+    fn queries_can_be_combined_across_groups() {
+        let groups = GroupIterator::new(indoc! {r#"
+            abc
+            ab
+
+            ba
+            c
+
+            bqa"#})
+        .collect::<Vec<Group>>();
+
+        assert_eq!(
+            common_across_groups(&groups, anyone_answered),
+            AnswerSet::from_answers("ab")
+        );
+        assert_eq!(
+            common_across_groups(&groups, everyone_answered),
+            AnswerSet::EMPTY
+        );
+        assert_eq!(
+            seen_across_groups(&groups, Group::answered_by_exactly_one),
+            AnswerSet::from_answers("abcq")
+        );
+        assert_eq!(
+            seen_across_groups(&groups, Group::answered_by_majority),
+            AnswerSet::from_answers("abq")
+        );
+    }
+
+    #[test]
+    fn survey_report_counts_questions_and_group_sizes() {
+        let report = SurveyReport::from_groups(GroupIterator::new(indoc! {r#"
+            abc
+
+            a
+            b
+            c
+
+            ab
+            ac
+
+            a
+            a
+            a
+            a
+
+            b"#}));
+
+        assert_eq!(report.total_people, 11);
+        assert_eq!(report.total_groups, 5);
+
+        let a = &report.questions[0];
+        assert_eq!(
+            (a.question, a.people, a.groups, a.unanimous_groups),
+            ('a', 8, 4, 3)
+        );
+        assert_eq!(report.unanimous_fraction(a), 0.6);
+        let z = &report.questions[25];
+        assert_eq!(
+            (z.question, z.people, z.groups, z.unanimous_groups),
+            ('z', 0, 0, 0)
+        );
+
+        assert_eq!(
+            report
+                .group_sizes
+                .into_iter()
+                .collect::<Vec<(usize, usize)>>(),
+            vec![(1, 2), (2, 1), (3, 1), (4, 1)]
+        );
+    }
+
+    #[test]
+    fn survey_report_can_be_exported() {
+        let report = SurveyReport::from_groups(GroupIterator::new("ab\nb\n\nb"));
+
+        let csv = report.questions_csv();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("question,people,groups,unanimous_groups,unanimous_fraction")
+        );
+        assert_eq!(lines.next(), Some("a,1,1,0,0.0000"));
+        assert_eq!(lines.next(), Some("b,3,2,2,1.0000"));
+        assert_eq!(lines.count(), 24);
+
+        assert_eq!(report.group_sizes_csv(), "group_size,groups\n1,1\n2,1\n");
+
+        let json = report.to_json();
+        assert!(json.starts_with(r#"{"total_people":3,"total_groups":2,"questions":[{"question":"a","people":1,"groups":1,"unanimous_groups":0,"unanimous_fraction":0.0000},"#));
+        assert!(json.ends_with(r#"],"group_sizes":{"1":1,"2":1}}"#));
+    }
+
+    #[test]
+    fn production_survey_report() {
+        let report =
+            SurveyReport::from_groups(GroupIterator::new(include_str!("inputs/day_six.txt")));
+
+        let groups: usize = report.questions.iter().map(|q| q.groups).sum();
+        let unanimous: usize = report.questions.iter().map(|q| q.unanimous_groups).sum();
+        assert_eq!(groups, 6542);
+        assert_eq!(unanimous, 3299);
+        assert_eq!(
+            report.group_sizes.values().sum::<usize>(),
+            report.total_groups
+        );
+    }
+
+    #[test]
+    fn strict_parsing_reports_every_problem() {
+        let parser = SurveyParser::new(AnswerSet::from_answers("abcdef"), ParseMode::Strict);
+        let errors = parser.parse("abc\naba\n\nAb\nx1\n").unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                SurveyError::DuplicateAnswer {
+                    line: 2,
+                    column: 3,
+                    answer: 'a'
+                },
+                SurveyError::InvalidAnswer {
+                    line: 4,
+                    column: 1,
+                    answer: 'A'
+                },
+                SurveyError::InvalidAnswer {
+                    line: 5,
+                    column: 1,
+                    answer: 'x'
+                },
+                SurveyError::InvalidAnswer {
+                    line: 5,
+                    column: 2,
+                    answer: '1'
+                },
+            ]
+        );
+        assert_eq!(errors[0].to_string(), "2:3: 'a' was already answered");
+        assert_eq!(errors[3].to_string(), "5:2: '1' is not a question");
+    }
+
+    #[test]
+    fn lenient_parsing_normalises_answers() {
+        let parser = SurveyParser::new(AnswerSet::ALL, ParseMode::Lenient);
+        let groups = parser.parse("aBA \nab\n\nC").unwrap();
+
+        assert_eq!(
+            groups,
+            vec![
+                ValidatedGroup {
+                    line_number: 1,
+                    members: vec![AnswerSet::from_answers("ab"), AnswerSet::from_answers("ab")],
+                },
+                ValidatedGroup {
+                    line_number: 4,
+                    members: vec![AnswerSet::from_answers("c")],
+                },
+            ]
+        );
+        assert!(parser.parse("ab\n3").is_err());
+    }
+
+    #[test]
+    fn production_input_passes_strict_validation() {
+        let parser = SurveyParser::new(AnswerSet::ALL, ParseMode::Strict);
+        let groups = parser.parse(include_str!("inputs/day_six.txt")).unwrap();
+
+        let anyone: usize = groups.iter().map(|g| g.anyone_answered().len()).sum();
+        let everyone: usize = groups.iter().map(|g| g.everyone_answered().len()).sum();
+        assert_eq!(anyone, 6542);
+        assert_eq!(everyone, 3299);
+    }
 }
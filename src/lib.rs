@@ -0,0 +1,10 @@
+//! The puzzles are run from the binary. This library only exists so `cargo fuzz` has something to
+//! link against, see fuzz/fuzz_targets.
+
+// fuzz_text is the only way in, so most of the module looks unused from here
+#[cfg(feature = "fuzz")]
+#[allow(dead_code)]
+mod day_eight;
+
+#[cfg(feature = "fuzz")]
+pub use crate::day_eight::fuzz_text;
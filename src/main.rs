@@ -17,8 +17,35 @@ mod day_three;
 mod day_two;
 
 fn main() {
-    let (_all, start, end) = Node::construct_graph_from_input(include_str!("inputs/day_ten.txt"));
+    let args = std::env::args().collect::<Vec<String>>();
 
-    let mut traverse = NodeTraverse { count: 0 };
-    traverse.count_paths_from_to(start, end);
+    match args.get(1).map(String::as_str) {
+        // cargo run -- bags src/inputs/day_seven.txt
+        Some("bags") => {
+            let path = args
+                .get(2)
+                .map_or("src/inputs/day_seven.txt", String::as_str);
+            if let Err(error) = day_seven::rule_shell(path) {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+        // cargo run -- debug src/inputs/day_eight.txt
+        Some("debug") => {
+            let path = args
+                .get(2)
+                .map_or("src/inputs/day_eight.txt", String::as_str);
+            if let Err(error) = day_eight::debugger_shell(path) {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+        _ => {
+            let (_all, start, end) =
+                Node::construct_graph_from_input(include_str!("inputs/day_ten.txt"));
+
+            let mut traverse = NodeTraverse { count: 0 };
+            traverse.count_paths_from_to(start, end);
+        }
+    }
 }
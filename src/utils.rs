@@ -1,22 +1,7 @@
 use std::fmt::Debug;
-use std::str::FromStr;
-
-pub fn split_once_at(source: &'static str, pattern: &'static str) -> (&'static str, &'static str) {
-    let index_of_first_occurance = source
-        .find(pattern)
-        .expect("Pattern does not exist in string");
-
-    (
-        &source[0..index_of_first_occurance],
-        &source[index_of_first_occurance + pattern.len()..],
-    )
-}
-
-pub fn split_into_array_by<'a>(source: &'a str, delimiter: &'static str) -> Vec<&'a str> {
-    let iterator = source.split(delimiter);
-
-    iterator.collect()
-}
+use std::io::{self, BufRead};
+use std::ops::Range;
+use std::str::{FromStr, SplitInclusive};
 
 pub fn parse_input_into_vec<T>(input: &'static str) -> Vec<T>
 where
@@ -29,3 +14,209 @@ where
 pub fn parse_input_into_vec_str(input: &'static str) -> Vec<&'static str> {
     input.lines().collect()
 }
+
+/// A run of consecutive non blank lines from a blank line separated input. `line_number` is the
+/// one based line the record starts on.
+#[derive(Debug, PartialEq)]
+pub struct Record<'a> {
+    pub line_number: usize,
+    pub lines: Vec<&'a str>,
+}
+
+// separator lines are allowed to carry stray whitespace
+fn is_record_separator(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+fn strip_line_ending(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+// what a raw line means for the record being gathered
+enum Split<'a> {
+    Line(&'a str),
+    Skip,
+    End(usize),
+}
+
+// The one place the splitting rules live. Fed every raw line in order, it tracks line numbers
+// and where the current record started, so both `Records` and `RecordReader` split the same way.
+#[derive(Debug, Default)]
+struct Splitter {
+    line_number: usize,
+    start: Option<usize>,
+}
+
+impl Splitter {
+    fn feed<'a>(&mut self, raw: &'a str) -> Split<'a> {
+        self.line_number += 1;
+        let line = strip_line_ending(raw);
+        if !is_record_separator(line) {
+            self.start.get_or_insert(self.line_number);
+            return Split::Line(line);
+        }
+
+        match self.start.take() {
+            Some(line_number) => Split::End(line_number),
+            None => Split::Skip,
+        }
+    }
+
+    // the start of the record cut short by the end of the input, if there is one
+    fn finish(&mut self) -> Option<usize> {
+        self.start.take()
+    }
+}
+
+/// Splits an in memory input into records, borrowing every line from the source
+#[derive(Debug)]
+pub struct Records<'a> {
+    lines: SplitInclusive<'a, char>,
+    splitter: Splitter,
+}
+
+impl<'a> Records<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            lines: source.split_inclusive('\n'),
+            splitter: Splitter::default(),
+        }
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Record<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut lines = vec![];
+        for raw in &mut self.lines {
+            match self.splitter.feed(raw) {
+                Split::Line(line) => lines.push(line),
+                Split::Skip => {}
+                Split::End(line_number) => return Some(Record { line_number, lines }),
+            }
+        }
+
+        self.splitter
+            .finish()
+            .map(|line_number| Record { line_number, lines })
+    }
+}
+
+/// Streams records out of any `BufRead`. Only the record currently being looked at is held in
+/// memory, so the yielded lines borrow from the reader and live until the next call.
+#[derive(Debug)]
+pub struct RecordReader<R> {
+    source: R,
+    buffer: String,
+    spans: Vec<Range<usize>>,
+    splitter: Splitter,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(source: R) -> Self {
+        Self {
+            source,
+            buffer: String::new(),
+            spans: vec![],
+            splitter: Splitter::default(),
+        }
+    }
+
+    pub fn next_record(&mut self) -> io::Result<Option<Record<'_>>> {
+        self.buffer.clear();
+        self.spans.clear();
+        let start = loop {
+            let line_start = self.buffer.len();
+            if self.source.read_line(&mut self.buffer)? == 0 {
+                break self.splitter.finish();
+            }
+
+            match self.splitter.feed(&self.buffer[line_start..]) {
+                Split::Line(line) => {
+                    let line_end = line_start + line.len();
+                    self.spans.push(line_start..line_end);
+                }
+                Split::Skip => self.buffer.truncate(line_start),
+                Split::End(line_number) => break Some(line_number),
+            }
+        };
+
+        let buffer = &self.buffer;
+        Ok(start.map(|line_number| Record {
+            line_number,
+            lines: self
+                .spans
+                .iter()
+                .map(|span| &buffer[span.clone()])
+                .collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::{Record, RecordReader, Records};
+    use std::io::Cursor;
+
+    #[test]
+    fn records_are_split_on_blank_lines() {
+        let input = "a\nb\n\n\nc\n  \t\nd\ne\n";
+        let records = Records::new(input).collect::<Vec<Record>>();
+
+        assert_eq!(
+            records,
+            vec![
+                Record {
+                    line_number: 1,
+                    lines: vec!["a", "b"]
+                },
+                Record {
+                    line_number: 5,
+                    lines: vec!["c"]
+                },
+                Record {
+                    line_number: 7,
+                    lines: vec!["d", "e"]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn records_tolerate_crlf_line_endings() {
+        let input = "a\r\nb\r\n \r\nc";
+        let records = Records::new(input).collect::<Vec<Record>>();
+
+        assert_eq!(records[0].lines, ["a", "b"]);
+        assert_eq!(records[1].lines, ["c"]);
+        assert_eq!(records[1].line_number, 4);
+    }
+
+    #[test]
+    fn record_reader_streams_from_buf_read() {
+        let mut reader = RecordReader::new(Cursor::new("\r\nab\r\ncd\r\n\t\r\n\r\nef"));
+
+        let first = reader.next_record().unwrap().unwrap();
+        assert_eq!(first.line_number, 2);
+        assert_eq!(first.lines, ["ab", "cd"]);
+
+        let second = reader.next_record().unwrap().unwrap();
+        assert_eq!(second.line_number, 6);
+        assert_eq!(second.lines, ["ef"]);
+
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn record_reader_agrees_with_in_memory_records() {
+        let input = include_str!("inputs/day_six.txt");
+        let mut reader = RecordReader::new(input.as_bytes());
+
+        for expected in Records::new(input) {
+            assert_eq!(reader.next_record().unwrap(), Some(expected));
+        }
+        assert_eq!(reader.next_record().unwrap(), None);
+    }
+}